@@ -0,0 +1,411 @@
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::UnorderedMap;
+use near_sdk::json_types::{Base58CryptoHash, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::{self, json};
+use near_sdk::{env, log, near_bindgen, AccountId, Gas, PanicOnDefault, Promise, PromiseResult};
+
+/// Storage key of the `tether_token` wasm that new instances are deployed with.
+const CODE_KEY: &[u8] = b"code";
+
+const GAS_FOR_TOKEN_INIT: Gas = Gas(50_000_000_000_000);
+const GAS_FOR_TOKEN_UPGRADE: Gas = Gas(100_000_000_000_000);
+const GAS_FOR_CALLBACK: Gas = Gas(10_000_000_000_000);
+
+/// Storage on top of the code that a new instance needs for its initial state.
+const TOKEN_STATE_BYTES: u128 = 100_000;
+/// Storage on top of the code that the token's `upgrade()` needs to record the deployment.
+/// Whatever the instance doesn't use is refunded to the factory.
+const TOKEN_UPGRADE_BYTES: u128 = 1_000;
+
+/// `Contract::new` arguments of the token.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenArgs {
+    pub owner_id: AccountId,
+    pub total_supply: U128,
+    pub metadata: FungibleTokenMetadata,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenInfo {
+    pub symbol: String,
+    pub owner_id: AccountId,
+    /// The code the instance runs, as far as the factory knows. Updated by `upgrade_tokens`.
+    pub code_hash: Base58CryptoHash,
+    pub created_at: U64,
+}
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct Factory {
+    owner_id: AccountId,
+    code_hash: Option<Base58CryptoHash>,
+    tokens: UnorderedMap<AccountId, TokenInfo>,
+}
+
+#[near_bindgen]
+impl Factory {
+    #[init]
+    pub fn new(owner_id: AccountId) -> Self {
+        Self {
+            owner_id,
+            code_hash: None,
+            tokens: UnorderedMap::new(b"t".to_vec()),
+        }
+    }
+
+    pub fn owner(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    /// Hash of the stored `tether_token` wasm, see `store_code`.
+    pub fn code_hash(&self) -> Option<Base58CryptoHash> {
+        self.code_hash
+    }
+
+    /// Deposit `create_token` requires with the currently stored code.
+    pub fn required_deposit(&self) -> U128 {
+        let code_len = self.code().len() as u128;
+        ((code_len + TOKEN_STATE_BYTES) * env::storage_byte_cost()).into()
+    }
+
+    /// Deposit `upgrade_tokens` requires per instance with the currently stored code. The token
+    /// keeps a copy of the code for rollbacks and charges its storage to the caller.
+    pub fn upgrade_deposit(&self) -> U128 {
+        let code_len = self.code().len() as u128;
+        ((code_len + TOKEN_UPGRADE_BYTES) * env::storage_byte_cost()).into()
+    }
+
+    pub fn tokens(&self, from_index: u64, limit: u64) -> Vec<(AccountId, TokenInfo)> {
+        self.tokens
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .collect()
+    }
+
+    pub fn token_count(&self) -> u64 {
+        self.tokens.len()
+    }
+
+    /// Creates `<prefix>.<factory>`, deploys the stored code and initializes it with `args`.
+    /// The attached deposit goes to the new account and has to cover its storage.
+    /// The factory is granted the `Upgrader` role so it can push coordinated upgrades; the token
+    /// owner can revoke it. Only the factory owner can create tokens.
+    #[payable]
+    pub fn create_token(&mut self, prefix: String, args: TokenArgs) -> Promise {
+        self.abort_if_not_owner();
+        let account_id: AccountId = format!("{}.{}", prefix, env::current_account_id())
+            .parse()
+            .unwrap_or_else(|_| env::panic_str("Invalid token account prefix"));
+        if self.tokens.get(&account_id).is_some() {
+            env::panic_str("The token already exists");
+        }
+        let deposit = env::attached_deposit();
+        if deposit < self.required_deposit().0 {
+            env::panic_str("The attached deposit doesn't cover the token storage");
+        }
+
+        let mut distribution = vec![];
+        if args.total_supply.0 > 0 {
+            distribution.push(json!({
+                "account_id": args.owner_id,
+                "amount": args.total_supply,
+            }));
+        }
+        let init_args = json!({
+            "config": {
+                "owner_id": args.owner_id,
+                "metadata": { "Custom": args.metadata },
+                "roles": [{ "account_id": env::current_account_id(), "role": "Upgrader" }],
+                "distribution": distribution,
+            }
+        });
+        let callback_args = json!({
+            "account_id": account_id,
+            "symbol": args.metadata.symbol,
+            "owner_id": args.owner_id,
+            "creator_id": env::predecessor_account_id(),
+            "deposit": U128(deposit),
+            "code_hash": self.code_hash,
+        });
+
+        Promise::new(account_id)
+            .create_account()
+            .transfer(deposit)
+            .deploy_contract(self.code())
+            .function_call(
+                "new_with_config".to_string(),
+                serde_json::to_vec(&init_args).unwrap(),
+                0,
+                GAS_FOR_TOKEN_INIT,
+            )
+            .then(Promise::new(env::current_account_id()).function_call(
+                "on_token_created".to_string(),
+                serde_json::to_vec(&callback_args).unwrap(),
+                0,
+                GAS_FOR_CALLBACK,
+            ))
+    }
+
+    /// Calls `upgrade` with the stored code on up to `limit` instances starting at `from_index`.
+    /// Call repeatedly with the next page to upgrade every instance the factory created.
+    /// Requires `upgrade_deposit` attached for every upgraded instance.
+    #[payable]
+    pub fn upgrade_tokens(&mut self, from_index: u64, limit: u64) -> Vec<AccountId> {
+        self.abort_if_not_owner();
+        let code = self.code();
+        let code_hash = self.code_hash.unwrap();
+        let accounts: Vec<AccountId> = self
+            .tokens
+            .keys()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .collect();
+        let deposit = self.upgrade_deposit().0;
+        if env::attached_deposit() < deposit * accounts.len() as u128 {
+            env::panic_str("The attached deposit doesn't cover the upgrade storage");
+        }
+        for account_id in accounts.iter() {
+            let callback_args = json!({ "account_id": account_id, "code_hash": code_hash });
+            Promise::new(account_id.clone())
+                .function_call(
+                    "upgrade".to_string(),
+                    code.clone(),
+                    deposit,
+                    GAS_FOR_TOKEN_UPGRADE,
+                )
+                .then(Promise::new(env::current_account_id()).function_call(
+                    "on_token_upgraded".to_string(),
+                    serde_json::to_vec(&callback_args).unwrap(),
+                    0,
+                    GAS_FOR_CALLBACK,
+                ));
+        }
+        accounts
+    }
+
+    #[private]
+    pub fn on_token_created(
+        &mut self,
+        account_id: AccountId,
+        symbol: String,
+        owner_id: AccountId,
+        creator_id: AccountId,
+        deposit: U128,
+        code_hash: Base58CryptoHash,
+    ) -> bool {
+        if is_promise_success() {
+            self.tokens.insert(
+                &account_id,
+                &TokenInfo {
+                    symbol,
+                    owner_id,
+                    code_hash,
+                    created_at: env::block_timestamp().into(),
+                },
+            );
+            log!("Created token @{}", account_id);
+            true
+        } else {
+            // The failed batch returned the deposit to the factory.
+            Promise::new(creator_id).transfer(deposit.0);
+            log!("Failed to create token @{}", account_id);
+            false
+        }
+    }
+
+    #[private]
+    pub fn on_token_upgraded(
+        &mut self,
+        account_id: AccountId,
+        code_hash: Base58CryptoHash,
+    ) -> bool {
+        let mut info = match self.tokens.get(&account_id) {
+            Some(info) => info,
+            None => return false,
+        };
+        if is_promise_success() {
+            info.code_hash = code_hash;
+            self.tokens.insert(&account_id, &info);
+            log!("Upgraded token @{}", account_id);
+            true
+        } else {
+            log!("Failed to upgrade token @{}", account_id);
+            false
+        }
+    }
+
+    fn code(&self) -> Vec<u8> {
+        env::storage_read(CODE_KEY)
+            .unwrap_or_else(|| env::panic_str("The token code is not stored"))
+    }
+
+    fn abort_if_not_owner(&self) {
+        if env::predecessor_account_id() != self.owner_id {
+            env::panic_str("This method might be called only by owner account")
+        }
+    }
+}
+
+fn is_promise_success() -> bool {
+    env::promise_results_count() == 1
+        && matches!(env::promise_result(0), PromiseResult::Successful(_))
+}
+
+/// Stores the `tether_token` wasm, taken as the raw input like the token's `upgrade()`.
+/// New instances and `upgrade_tokens` use this code.
+#[no_mangle]
+pub fn store_code() {
+    env::setup_panic_hook();
+
+    let mut factory: Factory = env::state_read().expect("Contract is not initialized");
+    factory.abort_if_not_owner();
+    let code = env::input().unwrap_or_else(|| env::panic_str("Expected wasm code in the input"));
+    env::storage_write(CODE_KEY, &code);
+    factory.code_hash = Some(env::sha256_array(&code).into());
+    env::state_write(&factory);
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::metadata::FT_METADATA_SPEC;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig, ONE_NEAR};
+
+    use super::*;
+
+    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id("factory.near".parse().unwrap())
+            .signer_account_id(predecessor_account_id.clone())
+            .predecessor_account_id(predecessor_account_id)
+            .prepaid_gas(Gas(300_000_000_000_000));
+        builder
+    }
+
+    fn token_args() -> TokenArgs {
+        TokenArgs {
+            owner_id: accounts(1),
+            total_supply: 1_000.into(),
+            metadata: FungibleTokenMetadata {
+                spec: FT_METADATA_SPEC.to_string(),
+                name: "Euro Tether".to_string(),
+                symbol: "EURt".to_string(),
+                icon: None,
+                reference: None,
+                reference_hash: None,
+                decimals: 6,
+            },
+        }
+    }
+
+    fn factory_with_code() -> Factory {
+        let mut factory = Factory::new(accounts(1));
+        env::storage_write(CODE_KEY, b"token wasm");
+        factory.code_hash = Some(env::sha256_array(b"token wasm").into());
+        factory
+    }
+
+    #[test]
+    fn test_create_token() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut factory = factory_with_code();
+        testing_env!(context.attached_deposit(10 * ONE_NEAR).build());
+        factory.create_token("eurt".to_string(), token_args());
+
+        testing_env!(
+            context
+                .predecessor_account_id("factory.near".parse().unwrap())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])]
+        );
+        assert!(factory.on_token_created(
+            "eurt.factory.near".parse().unwrap(),
+            "EURt".to_string(),
+            accounts(1),
+            accounts(1),
+            (10 * ONE_NEAR).into(),
+            env::sha256_array(b"token wasm").into(),
+        ));
+        let tokens = factory.tokens(0, 10);
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].0.as_str(), "eurt.factory.near");
+        assert_eq!(tokens[0].1.symbol, "EURt");
+    }
+
+    #[test]
+    fn test_create_token_failed() {
+        let context = get_context("factory.near".parse().unwrap());
+        testing_env!(
+            context.build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        let mut factory = factory_with_code();
+        assert!(!factory.on_token_created(
+            "eurt.factory.near".parse().unwrap(),
+            "EURt".to_string(),
+            accounts(1),
+            accounts(2),
+            (10 * ONE_NEAR).into(),
+            env::sha256_array(b"token wasm").into(),
+        ));
+        assert_eq!(factory.token_count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "The attached deposit doesn't cover the token storage")]
+    fn test_create_token_without_deposit() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut factory = factory_with_code();
+        factory.create_token("eurt".to_string(), token_args());
+    }
+
+    #[test]
+    #[should_panic(expected = "This method might be called only by owner account")]
+    fn test_create_token_by_user() {
+        let mut context = get_context(accounts(2));
+        testing_env!(context.attached_deposit(10 * ONE_NEAR).build());
+        let mut factory = factory_with_code();
+        factory.create_token("eurt".to_string(), token_args());
+    }
+
+    #[test]
+    #[should_panic(expected = "This method might be called only by owner account")]
+    fn test_upgrade_tokens_by_user() {
+        let context = get_context(accounts(2));
+        testing_env!(context.build());
+        let mut factory = factory_with_code();
+        factory.upgrade_tokens(0, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "The attached deposit doesn't cover the upgrade storage")]
+    fn test_upgrade_tokens_without_deposit() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut factory = factory_with_code();
+        factory.tokens.insert(
+            &"eurt.factory.near".parse().unwrap(),
+            &TokenInfo {
+                symbol: "EURt".to_string(),
+                owner_id: accounts(1),
+                code_hash: env::sha256_array(b"token wasm").into(),
+                created_at: U64(0),
+            },
+        );
+        factory.upgrade_tokens(0, 10);
+    }
+}
@@ -0,0 +1,63 @@
+//! `ft_on_transfer` receiver for the simulation tests. The `msg` of `ft_transfer_call` tells it
+//! what to do: `accept` keeps everything, `refund:<amount>` returns `amount`, `refund_all`
+//! returns everything and `panic` fails the call.
+
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde_json::json;
+use near_sdk::{env, log, near_bindgen, AccountId, Gas, PanicOnDefault, Promise, PromiseOrValue};
+
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct MockReceiver {
+    token_id: AccountId,
+}
+
+#[near_bindgen]
+impl MockReceiver {
+    #[init]
+    pub fn new(token_id: AccountId) -> Self {
+        Self { token_id }
+    }
+
+    /// Sends tokens held by this contract, so tests can check transfers made by a contract.
+    pub fn forward(&mut self, receiver_id: AccountId, amount: U128) -> Promise {
+        Promise::new(self.token_id.clone()).function_call(
+            "ft_transfer".to_string(),
+            json!({ "receiver_id": receiver_id, "amount": amount })
+                .to_string()
+                .into_bytes(),
+            1,
+            GAS_FOR_FT_TRANSFER,
+        )
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenReceiver for MockReceiver {
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.token_id,
+            "Only the token can call ft_on_transfer"
+        );
+        log!("Received {} from {}", amount.0, sender_id);
+        match msg.as_str() {
+            "accept" => PromiseOrValue::Value(U128(0)),
+            "refund_all" => PromiseOrValue::Value(amount),
+            "panic" => env::panic_str("ft_on_transfer panicked"),
+            _ => match msg.strip_prefix("refund:") {
+                Some(refund) => PromiseOrValue::Value(U128(refund.parse().unwrap())),
+                None => env::panic_str("Unknown msg"),
+            },
+        }
+    }
+}
@@ -0,0 +1,14 @@
+#!/bin/bash
+# Builds the token at a previous git ref into res/tether_token_previous.wasm, the code the
+# upgrade simulation test starts from. Defaults to the latest `v*` tag, or the first commit
+# when there is no tag.
+set -e
+
+REF=${1:-$(git describe --tags --abbrev=0 --match 'v*' 2>/dev/null || git rev-list --max-parents=0 HEAD)}
+WORKTREE=$(mktemp -d)
+
+git worktree add --detach "$WORKTREE" "$REF"
+(cd "$WORKTREE" && cargo build --target wasm32-unknown-unknown --release)
+mkdir -p res
+cp "$WORKTREE/target/wasm32-unknown-unknown/release/tether_token.wasm" res/tether_token_previous.wasm
+git worktree remove --force "$WORKTREE"
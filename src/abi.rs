@@ -0,0 +1,452 @@
+use crate::*;
+use near_sdk::serde_json::Value;
+
+/// Description of every public method: arguments, return type and who may call it.
+/// Generated from the sources by `test_abi_is_up_to_date`, which fails when the file is out of
+/// date; regenerate it with `UPDATE_ABI=1 cargo test abi`.
+const CONTRACT_ABI: &str = include_str!("abi.json");
+
+/// A NEP implemented by the contract, in the form of NEP-330 `standards`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StandardInfo {
+    pub standard: String,
+    pub version: String,
+}
+
+const SUPPORTED_STANDARDS: &[(&str, &str)] = &[
+    ("nep141", "1.0.0"),
+    ("nep145", "1.0.0"),
+    ("nep148", "1.0.0"),
+    ("nep297", "1.0.0"),
+];
+
+#[near_bindgen]
+impl Contract {
+    pub fn supported_standards(&self) -> Vec<StandardInfo> {
+        SUPPORTED_STANDARDS
+            .iter()
+            .map(|(standard, version)| StandardInfo {
+                standard: standard.to_string(),
+                version: version.to_string(),
+            })
+            .collect()
+    }
+
+    pub fn contract_abi(&self) -> Value {
+        near_sdk::serde_json::from_str(CONTRACT_ABI).expect("Invalid ABI")
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::serde_json::{json, Map};
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+    use std::collections::{BTreeMap, BTreeSet};
+    use syn::visit::{self, Visit};
+    use syn::{
+        Attribute, Block, Expr, ExprMethodCall, ExprPath, Fields, FnArg, GenericArgument, ImplItem,
+        Item, ItemEnum, Meta, NestedMeta, Pat, PathArguments, ReturnType, Signature, Type,
+        Visibility,
+    };
+
+    use super::*;
+    use crate::test_utils::get_context;
+
+    fn has_attr(attrs: &[Attribute], name: &str) -> bool {
+        attrs.iter().any(|attr| attr.path.is_ident(name))
+    }
+
+    /// Names inside the `#[name(...)]` attributes, e.g. the derived traits or the serde options.
+    fn attr_list(attrs: &[Attribute], name: &str) -> Vec<String> {
+        attrs
+            .iter()
+            .filter(|attr| attr.path.is_ident(name))
+            .filter_map(|attr| match attr.parse_meta() {
+                Ok(Meta::List(list)) => Some(list.nested),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|nested| match nested {
+                NestedMeta::Meta(meta) => meta.path().get_ident().map(|ident| ident.to_string()),
+                NestedMeta::Lit(_) => None,
+            })
+            .collect()
+    }
+
+    fn is_option(ty: &Type) -> bool {
+        matches!(ty, Type::Path(path) if path.path.segments.last().unwrap().ident == "Option")
+    }
+
+    fn nullable(schema: Value) -> Value {
+        json!({ "anyOf": [schema, { "type": "null" }] })
+    }
+
+    /// `{"type": "object"}` schema with a single required `name` property.
+    fn tagged(name: String, schema: Value) -> Value {
+        let mut properties = Map::new();
+        properties.insert(name.clone(), schema);
+        json!({ "type": "object", "properties": properties, "required": [name] })
+    }
+
+    /// Types of other crates that appear in the ABI, described like their serde output.
+    fn external_definition(name: &str) -> Option<Value> {
+        let string = json!({ "type": "string" });
+        match name {
+            "FungibleTokenMetadata" => Some(json!({
+                "type": "object",
+                "properties": {
+                    "spec": string,
+                    "name": string,
+                    "symbol": string,
+                    "icon": nullable(string.clone()),
+                    "reference": nullable(string.clone()),
+                    "reference_hash": nullable(string.clone()),
+                    "decimals": { "type": "integer", "minimum": 0 },
+                },
+                "required": ["spec", "name", "symbol", "decimals"],
+            })),
+            "StorageBalance" => Some(json!({
+                "type": "object",
+                "properties": { "total": string, "available": string },
+                "required": ["total", "available"],
+            })),
+            "StorageBalanceBounds" => Some(json!({
+                "type": "object",
+                "properties": { "min": string, "max": nullable(string.clone()) },
+                "required": ["min"],
+            })),
+            _ => None,
+        }
+    }
+
+    /// Method calls, with the last segment of a path first argument, and `ContractError`
+    /// variants in a method body.
+    #[derive(Default)]
+    struct Checks {
+        calls: Vec<(String, Option<String>)>,
+        errors: BTreeSet<String>,
+    }
+
+    impl<'ast> Visit<'ast> for Checks {
+        fn visit_expr_method_call(&mut self, call: &'ast ExprMethodCall) {
+            let arg = match call.args.first() {
+                Some(Expr::Path(arg)) => Some(arg.path.segments.last().unwrap().ident.to_string()),
+                _ => None,
+            };
+            self.calls.push((call.method.to_string(), arg));
+            visit::visit_expr_method_call(self, call);
+        }
+
+        fn visit_expr_path(&mut self, expr: &'ast ExprPath) {
+            let segments = &expr.path.segments;
+            if segments.len() == 2 && segments[0].ident == "ContractError" {
+                self.errors.insert(segments[1].ident.to_string());
+            }
+            visit::visit_expr_path(self, expr);
+        }
+    }
+
+    impl Checks {
+        fn calls(&self, method: &str) -> bool {
+            self.calls.iter().any(|(name, _)| name == method)
+        }
+
+        /// Who may call the method, from its attributes and the access checks.
+        fn permission(&self, attrs: &[Attribute]) -> String {
+            let role = self
+                .calls
+                .iter()
+                .find(|(name, _)| name == "abort_if_not_owner_or_role")
+                .and_then(|(_, role)| role.as_ref());
+            let permission = if let Some(role) = role {
+                return format!("owner or {} role", role);
+            } else if has_attr(attrs, "private") {
+                "contract itself"
+            } else if has_attr(attrs, "init") {
+                "anyone, once"
+            } else if self.calls("abort_if_not_owner_or_guardian") {
+                "owner or guardian"
+            } else if self.calls("abort_if_not_owner") {
+                "owner"
+            } else if self.errors.contains("NotEnoughApprovals") {
+                "owner, or guardian once a majority of guardians approved"
+            } else if self.errors.contains("NotGuardian") {
+                "guardian"
+            } else if self.errors.contains("NotProposedOwner") {
+                "proposed owner"
+            } else {
+                "anyone"
+            };
+            permission.to_string()
+        }
+    }
+
+    /// Builds the ABI from the parsed sources: JSON schemas of the arguments and return values,
+    /// with the serde types of the crate under `definitions`.
+    struct Generator {
+        types: BTreeMap<String, Vec<Item>>,
+        definitions: Map<String, Value>,
+    }
+
+    impl Generator {
+        fn schema(&mut self, ty: &Type) -> Value {
+            let path = match ty {
+                Type::Reference(reference) => return self.schema(&reference.elem),
+                Type::Tuple(tuple) => {
+                    let items: Vec<Value> = tuple.elems.iter().map(|ty| self.schema(ty)).collect();
+                    let len = items.len();
+                    return json!({ "type": "array", "items": items, "minItems": len, "maxItems": len });
+                }
+                Type::Path(path) => path,
+                _ => panic!("Unsupported type {:?}", ty),
+            };
+            let segment = path.path.segments.last().unwrap();
+            let name = segment.ident.to_string();
+            let args: Vec<&Type> = match &segment.arguments {
+                PathArguments::AngleBracketed(args) => args
+                    .args
+                    .iter()
+                    .filter_map(|arg| match arg {
+                        GenericArgument::Type(ty) => Some(ty),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => vec![],
+            };
+            match (name.as_str(), args.as_slice()) {
+                ("Option", [inner]) => nullable(self.schema(inner)),
+                ("Vec", [inner]) => json!({ "type": "array", "items": self.schema(inner) }),
+                ("PromiseOrValue", [inner]) => self.schema(inner),
+                // Whatever the called contract returns, or any JSON.
+                ("Promise", []) | ("Value", []) => json!({}),
+                ("AccountId", [])
+                | ("String", [])
+                | ("U128", [])
+                | ("U64", [])
+                | ("Base58CryptoHash", []) => json!({ "type": "string" }),
+                ("bool", []) => json!({ "type": "boolean" }),
+                ("u8", []) | ("u16", []) | ("u32", []) | ("u64", []) => {
+                    json!({ "type": "integer", "minimum": 0 })
+                }
+                (_, []) => {
+                    self.define(&name);
+                    json!({ "$ref": format!("#/definitions/{}", name) })
+                }
+                _ => panic!("Unsupported type {}", name),
+            }
+        }
+
+        fn define(&mut self, name: &str) {
+            if self.definitions.contains_key(name) {
+                return;
+            }
+            // Reserve the name first, the definition may refer to the type itself.
+            self.definitions.insert(name.to_string(), Value::Null);
+            let definition = match self.types.get(name).map(Vec::as_slice) {
+                Some([Item::Struct(item)]) => {
+                    let fields = item.fields.clone();
+                    self.fields_schema(&fields)
+                }
+                Some([Item::Enum(item)]) => {
+                    let item = item.clone();
+                    self.enum_schema(&item)
+                }
+                Some(_) => panic!("Ambiguous type {}", name),
+                None => {
+                    external_definition(name).unwrap_or_else(|| panic!("Unknown type {}", name))
+                }
+            };
+            self.definitions.insert(name.to_string(), definition);
+        }
+
+        fn fields_schema(&mut self, fields: &Fields) -> Value {
+            match fields {
+                Fields::Named(fields) => {
+                    let mut properties = Map::new();
+                    let mut required = vec![];
+                    for field in &fields.named {
+                        let name = field.ident.as_ref().unwrap().to_string();
+                        let serde = attr_list(&field.attrs, "serde");
+                        assert!(
+                            serde.iter().all(|option| option == "default"),
+                            "Unsupported serde options on {}: {:?}",
+                            name,
+                            serde
+                        );
+                        if serde.is_empty() && !is_option(&field.ty) {
+                            required.push(name.clone());
+                        }
+                        properties.insert(name, self.schema(&field.ty));
+                    }
+                    json!({ "type": "object", "properties": properties, "required": required })
+                }
+                Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                    self.schema(&fields.unnamed[0].ty)
+                }
+                _ => panic!("Unsupported fields {:?}", fields),
+            }
+        }
+
+        /// Externally tagged, the serde default.
+        fn enum_schema(&mut self, item: &ItemEnum) -> Value {
+            let serde = attr_list(&item.attrs, "serde");
+            assert!(
+                serde.iter().all(|option| option == "crate"),
+                "Unsupported serde options on {}: {:?}",
+                item.ident,
+                serde
+            );
+            let names: Vec<String> = item.variants.iter().map(|v| v.ident.to_string()).collect();
+            if item
+                .variants
+                .iter()
+                .all(|v| matches!(v.fields, Fields::Unit))
+            {
+                return json!({ "type": "string", "enum": names });
+            }
+            let variants: Vec<Value> = item
+                .variants
+                .iter()
+                .zip(names)
+                .map(|(variant, name)| match variant.fields {
+                    Fields::Unit => json!({ "type": "string", "enum": [name] }),
+                    _ => tagged(name, self.fields_schema(&variant.fields)),
+                })
+                .collect();
+            json!({ "oneOf": variants })
+        }
+
+        fn method(&mut self, attrs: &[Attribute], sig: &Signature, block: &Block) -> Value {
+            let init = has_attr(attrs, "init");
+            let mut receiver = None;
+            let mut args = vec![];
+            for input in &sig.inputs {
+                match input {
+                    FnArg::Receiver(input) => receiver = Some(input.mutability.is_some()),
+                    FnArg::Typed(input) => {
+                        let name = match &*input.pat {
+                            Pat::Ident(pat) => pat.ident.to_string(),
+                            pat => panic!("Unsupported argument {:?}", pat),
+                        };
+                        args.push(json!({ "name": name, "type_schema": self.schema(&input.ty) }));
+                    }
+                }
+            }
+            let kind = match (init, receiver) {
+                (true, _) => "init",
+                (false, Some(false)) => "view",
+                _ => "call",
+            };
+            let returns = match &sig.output {
+                ReturnType::Type(_, ty) if !init => self.schema(ty),
+                _ => Value::Null,
+            };
+            let mut checks = Checks::default();
+            checks.visit_block(block);
+            json!({
+                "name": sig.ident.to_string(),
+                "kind": kind,
+                "args": args,
+                "returns": returns,
+                "payable": has_attr(attrs, "payable"),
+                "permission": checks.permission(attrs),
+            })
+        }
+    }
+
+    /// The ABI of the methods exported by `src/*.rs`: the public functions of `#[near_bindgen]`
+    /// impls, all functions of `#[near_bindgen]` trait impls, and `#[no_mangle]` functions.
+    fn generate_abi() -> Value {
+        let dir = std::path::Path::new(file!()).parent().unwrap();
+        let mut files = vec![];
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension() == Some("rs".as_ref()) {
+                let source = std::fs::read_to_string(&path).unwrap();
+                files.push(syn::parse_file(&source).unwrap());
+            }
+        }
+        let items: Vec<&Item> = files.iter().flat_map(|file| &file.items).collect();
+
+        let mut generator = Generator {
+            types: BTreeMap::new(),
+            definitions: Map::new(),
+        };
+        for item in &items {
+            let (ident, attrs) = match item {
+                Item::Struct(item) => (&item.ident, &item.attrs),
+                Item::Enum(item) => (&item.ident, &item.attrs),
+                _ => continue,
+            };
+            let derives = attr_list(attrs, "derive");
+            if derives
+                .iter()
+                .any(|d| d == "Serialize" || d == "Deserialize")
+            {
+                let types = generator.types.entry(ident.to_string()).or_default();
+                types.push((*item).clone());
+            }
+        }
+
+        let mut methods = BTreeMap::new();
+        for item in &items {
+            match item {
+                Item::Impl(item) if has_attr(&item.attrs, "near_bindgen") => {
+                    for impl_item in &item.items {
+                        let method = match impl_item {
+                            ImplItem::Method(method) => method,
+                            _ => continue,
+                        };
+                        if item.trait_.is_some() || matches!(method.vis, Visibility::Public(_)) {
+                            let abi = generator.method(&method.attrs, &method.sig, &method.block);
+                            methods.insert(method.sig.ident.to_string(), abi);
+                        }
+                    }
+                }
+                Item::Fn(item) if has_attr(&item.attrs, "no_mangle") => {
+                    let mut abi = generator.method(&item.attrs, &item.sig, &item.block);
+                    // Raw functions take the wasm as the input and don't check the deposit.
+                    abi["payable"] = true.into();
+                    abi["input"] = "raw wasm code".into();
+                    methods.insert(item.sig.ident.to_string(), abi);
+                }
+                _ => {}
+            }
+        }
+
+        json!({
+            "name": "tether_token",
+            "schema_version": "2",
+            "methods": methods.into_values().collect::<Vec<_>>(),
+            "definitions": generator.definitions,
+        })
+    }
+
+    /// Run with `UPDATE_ABI=1` to write the generated ABI to `src/abi.json`.
+    #[test]
+    fn test_abi_is_up_to_date() {
+        let generated = generate_abi();
+        if std::env::var_os("UPDATE_ABI").is_some() {
+            let path = std::path::Path::new(file!()).with_file_name("abi.json");
+            let json = near_sdk::serde_json::to_string_pretty(&generated).unwrap();
+            std::fs::write(path, json + "\n").unwrap();
+            return;
+        }
+        let committed: Value = near_sdk::serde_json::from_str(CONTRACT_ABI).unwrap();
+        assert!(
+            committed == generated,
+            "src/abi.json is out of date, regenerate it with `UPDATE_ABI=1 cargo test abi`"
+        );
+    }
+
+    #[test]
+    fn test_supported_standards() {
+        testing_env!(get_context(accounts(1)).build());
+        let contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        let standards = contract.supported_standards();
+        assert_eq!(standards[0].standard, "nep141");
+        assert_eq!(contract.contract_abi()["name"], "tether_token");
+    }
+}
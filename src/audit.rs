@@ -0,0 +1,200 @@
+use crate::*;
+
+/// A ledger audit: the holders before `next_index` have `holders_balance` in total.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Audit {
+    next_index: u64,
+    holders_balance: Balance,
+    /// Set when a balance changes between two steps, which makes the running sum useless.
+    interrupted: bool,
+    /// Whether the sum matched the total supply, set by the step that reaches the last holder.
+    consistent: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AuditProgressView {
+    /// `from_index` of the next `audit_step`.
+    pub next_index: u64,
+    pub holder_count: u64,
+    pub holders_balance: U128,
+    pub completed: bool,
+    /// Whether `holders_balance` matches the total supply, once the audit is completed.
+    pub consistent: Option<bool>,
+}
+
+impl Contract {
+    /// Must be called after every balance change, so an audit doesn't mix balances from before
+    /// and after the change.
+    pub(crate) fn internal_interrupt_audit(&mut self) {
+        if let Some(audit) = self.audit.as_mut() {
+            audit.interrupted = true;
+        }
+    }
+
+    fn audit_view(&self, audit: &Audit) -> AuditProgressView {
+        AuditProgressView {
+            next_index: audit.next_index,
+            holder_count: self.holders.len(),
+            holders_balance: audit.holders_balance.into(),
+            completed: audit.consistent.is_some(),
+            consistent: audit.consistent,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Adds the balances of up to `limit` holders, starting at `from_index`, to the running sum
+    /// of the audit. `from_index` 0 starts a new audit, any other value has to be the
+    /// `next_index` returned by the previous step. The step that reaches the last holder logs an
+    /// `audit_result` event and returns, and keeps for `audit_progress`, whether the sum matches
+    /// the total supply. Any balance change between two steps interrupts the audit, so on a live
+    /// token an audit that takes more than one step can only complete while the contract is
+    /// paused. Works while paused, but not during a migration, which may still be building the
+    /// holder index.
+    pub fn audit_step(&mut self, from_index: u64, limit: u64) -> AuditProgressView {
+        self.abort_if_not_owner();
+        if self.status == ContractStatus::Migrating {
+            ContractError::Migrating.panic();
+        }
+        let mut audit = match self.audit.take() {
+            _ if from_index == 0 => Audit {
+                next_index: 0,
+                holders_balance: 0,
+                interrupted: false,
+                consistent: None,
+            },
+            Some(audit) if audit.interrupted => ContractError::AuditInterrupted.panic(),
+            Some(audit) if audit.consistent.is_none() && audit.next_index == from_index => audit,
+            _ => ContractError::AuditOutOfOrder.panic_with(&from_index.to_string()),
+        };
+        for account_id in self
+            .holders
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+        {
+            audit.holders_balance += self.token.accounts.get(&account_id).unwrap_or(0);
+            audit.next_index += 1;
+        }
+
+        let holder_count = self.holders.len();
+        if audit.next_index >= holder_count {
+            let total_supply = self.token.total_supply;
+            event::emit::audit_result(
+                env::block_height(),
+                holder_count,
+                audit.holders_balance,
+                total_supply,
+            );
+            audit.consistent = Some(audit.holders_balance == total_supply);
+        }
+        let view = self.audit_view(&audit);
+        self.audit = Some(audit);
+        view
+    }
+
+    /// The audit in progress, or the result of the last completed one.
+    pub fn audit_progress(&self) -> Option<AuditProgressView> {
+        self.audit.as_ref().map(|audit| self.audit_view(audit))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(1))
+            .block_index(42);
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        for account_id in [accounts(2), accounts(3), accounts(4)].iter() {
+            contract.token.internal_register_account(account_id);
+            contract.mint(account_id, 100.into());
+        }
+        (context, contract)
+    }
+
+    #[test]
+    fn test_audit_in_steps() {
+        let (_, mut contract) = setup();
+        let view = contract.audit_step(0, 3);
+        assert_eq!(view.next_index, 3);
+        assert!(!view.completed);
+        assert_eq!(contract.audit_progress(), Some(view));
+
+        let view = contract.audit_step(3, 3);
+        assert_eq!(view.holders_balance, U128(1_300));
+        assert!(view.completed);
+        assert_eq!(view.consistent, Some(true));
+        assert_eq!(contract.audit_progress(), Some(view));
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"tether_token","version":"1.0.0","event":"audit_result","data":[{"block_height":"42","holder_count":"4","holders_balance":"1300","total_supply":"1300","consistent":true}]}"#
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_AUDIT_INTERRUPTED")]
+    fn test_balance_change_interrupts_audit() {
+        let (mut context, mut contract) = setup();
+        contract.audit_step(0, 2);
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.ft_transfer(accounts(3), 10.into(), None);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(0)
+            .build());
+        contract.audit_step(2, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_AUDIT_INTERRUPTED")]
+    fn test_force_close_interrupts_audit() {
+        let (mut context, mut contract) = setup();
+        contract.audit_step(0, 2);
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        assert!(contract.storage_unregister(Some(true)));
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(0)
+            .build());
+        contract.audit_step(2, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_AUDIT_OUT_OF_ORDER")]
+    fn test_audit_out_of_order() {
+        let (_, mut contract) = setup();
+        contract.audit_step(0, 2);
+        contract.audit_step(3, 2);
+    }
+
+    #[test]
+    fn test_audit_mismatch() {
+        let (_, mut contract) = setup();
+        contract.token.total_supply += 5;
+        let view = contract.audit_step(0, 10);
+        assert!(view.completed);
+        assert_eq!(view.consistent, Some(false));
+        assert_eq!(contract.audit_progress(), Some(view));
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"tether_token","version":"1.0.0","event":"audit_result","data":[{"block_height":"42","holder_count":"4","holders_balance":"1300","total_supply":"1305","consistent":false}]}"#
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use crate::*;
+use near_sdk::assert_one_yocto;
+use near_sdk::collections::LookupSet;
+use near_sdk::json_types::U64;
+
+/// Longest accepted `source_tx_hash` or `destination_address`.
+const MAX_BRIDGE_REFERENCE_LEN: usize = 128;
+
+/// A chain the token is bridged with.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BridgeChain {
+    /// Maximum of `outstanding`, `None` for no cap.
+    pub cap: Option<U128>,
+    /// Minted from the chain and not yet burned back to it, i.e. what has to be locked there.
+    pub outstanding: U128,
+    /// Stops `bridge_mint` from and `bridge_burn` to the chain.
+    pub paused: bool,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Bridge {
+    chains: UnorderedMap<String, BridgeChain>,
+    /// `(source_chain, source_tx_hash)` of every `bridge_mint`, with the hash normalized by
+    /// `normalize_tx_hash`.
+    processed: LookupSet<(String, String)>,
+    burn_nonce: u64,
+}
+
+impl Bridge {
+    pub(crate) fn new() -> Self {
+        Self {
+            chains: UnorderedMap::new(b"u".to_vec()),
+            processed: LookupSet::new(b"z".to_vec()),
+            burn_nonce: 0,
+        }
+    }
+
+    fn chain(&self, chain_id: &str) -> BridgeChain {
+        self.chains
+            .get(&chain_id.to_string())
+            .unwrap_or_else(|| ContractError::UnknownBridgeChain.panic_with(chain_id))
+    }
+
+    /// Like `chain`, but also aborts if the chain is paused.
+    fn active_chain(&self, chain_id: &str) -> BridgeChain {
+        let chain = self.chain(chain_id);
+        if chain.paused {
+            ContractError::BridgeChainPaused.panic_with(chain_id);
+        }
+        chain
+    }
+}
+
+fn abort_if_invalid_reference(value: &str) {
+    if value.is_empty() || value.len() > MAX_BRIDGE_REFERENCE_LEN {
+        ContractError::InvalidBridgeRequest.panic_with(value);
+    }
+}
+
+/// Hex hashes without the `0x` prefix and in lowercase, so one transaction can't be minted
+/// twice under different spellings. Other encodings, e.g. base58, are case-sensitive and kept.
+fn normalize_tx_hash(tx_hash: &str) -> String {
+    let hex = tx_hash
+        .strip_prefix("0x")
+        .or_else(|| tx_hash.strip_prefix("0X"))
+        .unwrap_or(tx_hash);
+    if hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        hex.to_ascii_lowercase()
+    } else {
+        tx_hash.to_string()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Adds a chain or changes its cap. Lowering the cap below `outstanding` only stops
+    /// further mints from the chain.
+    pub fn set_bridge_chain(&mut self, chain_id: String, cap: Option<U128>) {
+        self.abort_if_not_owner();
+        let chain = match self.bridge.chains.get(&chain_id) {
+            Some(chain) => BridgeChain { cap, ..chain },
+            None => BridgeChain {
+                cap,
+                outstanding: 0.into(),
+                paused: false,
+            },
+        };
+        self.bridge.chains.insert(&chain_id, &chain);
+    }
+
+    pub fn pause_bridge_chain(&mut self, chain_id: String) {
+        self.abort_if_not_owner_or_guardian();
+        let mut chain = self.bridge.chain(&chain_id);
+        chain.paused = true;
+        self.bridge.chains.insert(&chain_id, &chain);
+    }
+
+    pub fn resume_bridge_chain(&mut self, chain_id: String) {
+        self.abort_if_not_owner_or_guardian();
+        let mut chain = self.bridge.chain(&chain_id);
+        chain.paused = false;
+        self.bridge.chains.insert(&chain_id, &chain);
+    }
+
+    pub fn bridge_chain(&self, chain_id: String) -> Option<BridgeChain> {
+        self.bridge.chains.get(&chain_id)
+    }
+
+    pub fn bridge_chains(&self, from_index: u64, limit: u64) -> Vec<(String, BridgeChain)> {
+        self.bridge
+            .chains
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .collect()
+    }
+
+    pub fn is_bridge_tx_processed(&self, source_chain: String, source_tx_hash: String) -> bool {
+        self.bridge
+            .processed
+            .contains(&(source_chain, normalize_tx_hash(&source_tx_hash)))
+    }
+
+    /// Mints tokens that were locked on `source_chain` in `source_tx_hash`. Each source
+    /// transaction can be minted only once, and the tokens outstanding from a chain can't
+    /// exceed its cap. The caller pays for storing the processed transaction with the attached
+    /// deposit, the rest is refunded.
+    #[payable]
+    pub fn bridge_mint(
+        &mut self,
+        recipient: AccountId,
+        amount: U128,
+        source_chain: String,
+        source_tx_hash: String,
+    ) {
+        self.abort_if_not_owner_or_role(Role::Bridge);
+        self.abort_if_pause();
+        self.abort_if_blacklisted(&recipient);
+        let source_tx_hash = normalize_tx_hash(&source_tx_hash);
+        abort_if_invalid_reference(&source_tx_hash);
+        if amount.0 == 0 {
+            ContractError::InvalidBridgeRequest.panic_with("zero amount");
+        }
+        let mut chain = self.bridge.active_chain(&source_chain);
+        let outstanding = chain.outstanding.0.saturating_add(amount.0);
+        if matches!(chain.cap, Some(cap) if outstanding > cap.0) {
+            ContractError::BridgeCapExceeded.panic_with(&source_chain);
+        }
+        let source = (source_chain, source_tx_hash);
+        let initial_storage_usage = env::storage_usage();
+        if !self.bridge.processed.insert(&source) {
+            ContractError::BridgeTxProcessed.panic_with(&source.1);
+        }
+        self.internal_settle_storage(initial_storage_usage);
+        let (source_chain, source_tx_hash) = source;
+        chain.outstanding = outstanding.into();
+        self.bridge.chains.insert(&source_chain, &chain);
+
+        self.internal_mint(&recipient, amount.0, None);
+        event::emit::bridge_mint(&recipient, amount.0, source_chain, source_tx_hash);
+    }
+
+    /// Burns tokens of the caller to release them on `destination_chain`. Returns the nonce
+    /// of the `bridge_burn` event, which relayers use to release every burn once.
+    #[payable]
+    pub fn bridge_burn(
+        &mut self,
+        amount: U128,
+        destination_chain: String,
+        destination_address: String,
+    ) -> U64 {
+        assert_one_yocto();
+        self.abort_if_pause();
+        let sender_id = env::predecessor_account_id();
+        self.abort_if_blacklisted(&sender_id);
+        abort_if_invalid_reference(&destination_address);
+        if amount.0 == 0 {
+            ContractError::InvalidBridgeRequest.panic_with("zero amount");
+        }
+        let mut chain = self.bridge.active_chain(&destination_chain);
+        if amount.0 > chain.outstanding.0 {
+            ContractError::BridgeOutstandingExceeded.panic_with(&destination_chain);
+        }
+        chain.outstanding = (chain.outstanding.0 - amount.0).into();
+        self.bridge.chains.insert(&destination_chain, &chain);
+        self.bridge.burn_nonce += 1;
+        let nonce = self.bridge.burn_nonce;
+
+        self.internal_checkpoint_balance(&sender_id);
+        self.internal_checkpoint_supply();
+        self.token.internal_withdraw(&sender_id, amount.0);
+        self.internal_sync_holder(&sender_id);
+        event::emit::ft_burn(&sender_id, amount.0, None);
+        event::emit::bridge_burn(
+            &sender_id,
+            amount.0,
+            destination_chain,
+            destination_address,
+            nonce,
+        );
+        nonce.into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 0.into());
+        contract.grant_role(accounts(3), Role::Bridge);
+        contract.set_bridge_chain("ethereum".to_string(), Some(1_000.into()));
+        contract.token.internal_register_account(&accounts(2));
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(near_sdk::ONE_NEAR)
+            .build());
+        (context, contract)
+    }
+
+    fn mint(contract: &mut Contract, amount: Balance, tx_hash: &str) {
+        contract.bridge_mint(
+            accounts(2),
+            amount.into(),
+            "ethereum".to_string(),
+            tx_hash.to_string(),
+        );
+    }
+
+    #[test]
+    fn test_bridge_mint_and_burn() {
+        let (mut context, mut contract) = setup();
+        mint(&mut contract, 600, "0xaa");
+        assert_eq!(contract.ft_balance_of(accounts(2)), U128(600));
+        assert!(contract.is_bridge_tx_processed("ethereum".to_string(), "0xaa".to_string()));
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"tether_token","version":"1.0.0","event":"bridge_mint","data":[{"recipient":"charlie","amount":"600","source_chain":"ethereum","source_tx_hash":"aa"}]}"#
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        let nonce = contract.bridge_burn(200.into(), "ethereum".to_string(), "0xbb".to_string());
+        assert_eq!(nonce, U64(1));
+        assert_eq!(contract.ft_total_supply(), U128(400));
+        assert_eq!(
+            contract
+                .bridge_chain("ethereum".to_string())
+                .unwrap()
+                .outstanding,
+            U128(400)
+        );
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"tether_token","version":"1.0.0","event":"bridge_burn","data":[{"sender_id":"charlie","amount":"200","destination_chain":"ethereum","destination_address":"0xbb","nonce":"1"}]}"#
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BRIDGE_TX_PROCESSED")]
+    fn test_bridge_mint_replay() {
+        let (_, mut contract) = setup();
+        mint(&mut contract, 100, "0xaa");
+        mint(&mut contract, 100, "0xaa");
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BRIDGE_TX_PROCESSED")]
+    fn test_bridge_mint_replay_with_other_spelling() {
+        let (_, mut contract) = setup();
+        mint(&mut contract, 100, "0xAA");
+        assert!(contract.is_bridge_tx_processed("ethereum".to_string(), "aa".to_string()));
+        mint(&mut contract, 100, "aa");
+    }
+
+    #[test]
+    fn test_normalize_tx_hash() {
+        assert_eq!(normalize_tx_hash("0xABcd"), "abcd");
+        assert_eq!(normalize_tx_hash("0XAB"), "ab");
+        assert_eq!(
+            normalize_tx_hash("5VfydnLu4XwV2H2dLHPv22JxhLbYJruaM9YTaGY30TZj"),
+            "5VfydnLu4XwV2H2dLHPv22JxhLbYJruaM9YTaGY30TZj"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The attached deposit is less than the storage cost")]
+    fn test_bridge_mint_without_deposit() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(0).build());
+        mint(&mut contract, 100, "0xaa");
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BRIDGE_CAP_EXCEEDED")]
+    fn test_bridge_chain_cap() {
+        let (_, mut contract) = setup();
+        mint(&mut contract, 600, "0xaa");
+        mint(&mut contract, 401, "0xab");
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_MISSING_ROLE")]
+    fn test_bridge_mint_requires_role() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        mint(&mut contract, 100, "0xaa");
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BANNED")]
+    fn test_bridge_mint_to_banned_account() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.add_to_blacklist(&accounts(2));
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        mint(&mut contract, 100, "0xaa");
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BRIDGE_CHAIN_PAUSED")]
+    fn test_paused_bridge_chain() {
+        let (mut context, mut contract) = setup();
+        mint(&mut contract, 100, "0xaa");
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.pause_bridge_chain("ethereum".to_string());
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.bridge_burn(100.into(), "ethereum".to_string(), "0xbb".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BRIDGE_OUTSTANDING_EXCEEDED")]
+    fn test_bridge_burn_over_outstanding() {
+        let (mut context, mut contract) = setup();
+        mint(&mut contract, 100, "0xaa");
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.set_bridge_chain("tron".to_string(), None);
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.bridge_burn(100.into(), "tron".to_string(), "T9yD".to_string());
+    }
+}
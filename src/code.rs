@@ -0,0 +1,555 @@
+use crate::*;
+use near_sdk::collections::Vector;
+use near_sdk::json_types::{Base58CryptoHash, U64};
+use near_sdk::{CryptoHash, Promise};
+
+/// Storage key of the `CodeRegistry`. It is kept outside of the `Contract` struct so the history
+/// survives state layout changes and rollbacks to binaries that don't know about it.
+const CODE_REGISTRY_KEY: &[u8] = b"r";
+/// Storage key of the `StagedCode`. It is not part of the `CodeRegistry`, whose layout has to
+/// stay readable by every binary that may be rolled back to.
+const STAGED_CODE_KEY: &[u8] = b"C";
+
+const GAS_FOR_DOWNGRADE_STATE: Gas = Gas(20_000_000_000_000);
+const DEPLOY_GAS_LEFTOVER: Gas = Gas(10_000_000_000_000);
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CodeRecord {
+    /// `version()` of the deployed binary.
+    pub version: String,
+    /// `None` for binaries deployed without going through `upgrade()`.
+    pub code_hash: Option<Base58CryptoHash>,
+    pub state_version: StateVersion,
+    pub timestamp: U64,
+    pub block_height: U64,
+}
+
+/// Deployed code history plus the wasm blobs of the current and the previous deployment, which
+/// are what `rollback()` needs.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct CodeRegistry {
+    history: Vector<CodeRecord>,
+    blobs: LookupMap<CryptoHash, Vec<u8>>,
+    pending_code_hash: Option<CryptoHash>,
+    rollback_approvals: UnorderedSet<AccountId>,
+}
+
+impl CodeRegistry {
+    pub(crate) fn load() -> Self {
+        match env::storage_read(CODE_REGISTRY_KEY) {
+            Some(bytes) => CodeRegistry::try_from_slice(&bytes)
+                .unwrap_or_else(|_| ContractError::InvalidCodeRegistry.panic()),
+            None => Self {
+                history: Vector::new(b"h".to_vec()),
+                blobs: LookupMap::new(b"w".to_vec()),
+                pending_code_hash: None,
+                rollback_approvals: UnorderedSet::new(b"k".to_vec()),
+            },
+        }
+    }
+
+    pub(crate) fn save(&self) {
+        env::storage_write(
+            CODE_REGISTRY_KEY,
+            &self
+                .try_to_vec()
+                .expect("Unable to serialize code registry"),
+        );
+    }
+
+    /// Stores the blob that is about to be deployed. The hash is picked up by
+    /// `record_deployment` once the new binary runs `migrate`.
+    pub(crate) fn stage_deployment(&mut self, code: &[u8]) {
+        let code_hash = env::sha256_array(code);
+        if !self.blobs.contains_key(&code_hash) {
+            self.blobs.insert(&code_hash, &code.to_vec());
+        }
+        self.pending_code_hash = Some(code_hash);
+    }
+
+    /// Appends the running binary to the history and drops blobs that can no longer be rolled
+    /// back to.
+    pub(crate) fn record_deployment(&mut self) {
+        let code_hash = self.pending_code_hash.take();
+        self.history.push(&CodeRecord {
+            version: format!("{}:{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            code_hash: code_hash.map(Base58CryptoHash::from),
+            state_version: StateVersion::CURRENT,
+            timestamp: env::block_timestamp().into(),
+            block_height: env::block_height().into(),
+        });
+        self.rollback_approvals.clear();
+
+        let len = self.history.len();
+        if len < 3 {
+            return;
+        }
+        let retained = [self.code_hash_at(len - 1), self.code_hash_at(len - 2)];
+        if let Some(expired) = self.code_hash_at(len - 3) {
+            if !retained.contains(&Some(expired)) {
+                self.blobs.remove(&expired);
+            }
+        }
+    }
+
+    fn code_hash_at(&self, index: u64) -> Option<CryptoHash> {
+        self.history
+            .get(index)
+            .and_then(|record| record.code_hash)
+            .map(CryptoHash::from)
+    }
+
+    fn previous_deployment(&self) -> CodeRecord {
+        let len = self.history.len();
+        if len < 2 {
+            ContractError::NoPreviousCode.panic();
+        }
+        self.history.get(len - 2).unwrap()
+    }
+}
+
+/// Chunks uploaded through `stage_code`, in order.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct StagedCode {
+    chunks: Vector<Vec<u8>>,
+}
+
+impl StagedCode {
+    fn load() -> Self {
+        match env::storage_read(STAGED_CODE_KEY) {
+            Some(bytes) => StagedCode::try_from_slice(&bytes)
+                .unwrap_or_else(|_| ContractError::InvalidCodeRegistry.panic()),
+            None => Self {
+                chunks: Vector::new(b"s".to_vec()),
+            },
+        }
+    }
+
+    fn save(&self) {
+        env::storage_write(
+            STAGED_CODE_KEY,
+            &self.try_to_vec().expect("Unable to serialize staged code"),
+        );
+    }
+
+    fn code(&self) -> Option<Vec<u8>> {
+        if self.chunks.is_empty() {
+            return None;
+        }
+        Some(self.chunks.iter().flatten().collect())
+    }
+}
+
+/// Gas left for the last call of a deployment batch once `reserved` is set aside for the rest.
+pub(crate) fn remaining_gas(reserved: Gas) -> Gas {
+    let gas = env::prepaid_gas()
+        .0
+        .saturating_sub(env::used_gas().0)
+        .saturating_sub(reserved.0);
+    if gas == 0 {
+        ContractError::NotEnoughGas.panic();
+    }
+    Gas(gas)
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn code_history(&self) -> Vec<CodeRecord> {
+        CodeRegistry::load().history.to_vec()
+    }
+
+    pub fn rollback_approvals(&self) -> Vec<AccountId> {
+        CodeRegistry::load().rollback_approvals.to_vec()
+    }
+
+    /// Guardian vote to roll back to the previous code. Approvals are reset on every deployment.
+    pub fn approve_rollback(&mut self) {
+        let guardian = env::predecessor_account_id();
+        if !self.guardians.contains(&guardian) {
+            ContractError::NotGuardian.panic();
+        }
+        let mut registry = CodeRegistry::load();
+        registry.previous_deployment();
+        registry.rollback_approvals.insert(&guardian);
+        registry.save();
+    }
+
+    /// Redeploys the previous code. Can be called by the owner, or by a guardian once more than
+    /// half of the guardians have approved the rollback. Approvals of removed guardians don't
+    /// count.
+    /// The state is first converted to the layout of the previous code by `downgrade_state`,
+    /// then the code is deployed and its `migrate` is called, all in one batch.
+    pub fn rollback(&mut self) -> Promise {
+        let caller = env::predecessor_account_id();
+        let mut registry = CodeRegistry::load();
+        if caller != self.owner_id {
+            if !self.guardians.contains(&caller) {
+                ContractError::NotOwnerOrGuardian.panic();
+            }
+            let quorum = self.guardians.len() / 2 + 1;
+            let approvals = registry
+                .rollback_approvals
+                .iter()
+                .filter(|guardian| self.guardians.contains(guardian))
+                .count() as u64;
+            if approvals < quorum {
+                ContractError::NotEnoughApprovals.panic();
+            }
+        }
+        if self.status == ContractStatus::Migrating {
+            ContractError::MigrationInProgress.panic();
+        }
+
+        let previous = registry.previous_deployment();
+        let code_hash: CryptoHash = previous
+            .code_hash
+            .unwrap_or_else(|| ContractError::PreviousCodeNotStored.panic())
+            .into();
+        let code = registry
+            .blobs
+            .get(&code_hash)
+            .unwrap_or_else(|| ContractError::PreviousCodeNotStored.panic());
+        registry.pending_code_hash = Some(code_hash);
+        registry.rollback_approvals.clear();
+        registry.save();
+
+        log!("Rolling back to {}", previous.version);
+        let downgrade_args = near_sdk::serde_json::to_vec(&DowngradeArgs {
+            state_version: previous.state_version,
+        })
+        .unwrap();
+        Promise::new(env::current_account_id())
+            .function_call(
+                "downgrade_state".to_string(),
+                downgrade_args,
+                0,
+                GAS_FOR_DOWNGRADE_STATE,
+            )
+            .deploy_contract(code)
+            .function_call(
+                "migrate".to_string(),
+                vec![],
+                0,
+                remaining_gas(GAS_FOR_DOWNGRADE_STATE + DEPLOY_GAS_LEFTOVER),
+            )
+    }
+
+    pub fn staged_code_hash(&self) -> Option<Base58CryptoHash> {
+        StagedCode::load()
+            .code()
+            .map(|code| env::sha256_array(&code).into())
+    }
+
+    /// Drops the staged code and refunds the released storage to the owner.
+    pub fn clear_staged_code(&mut self) {
+        self.abort_if_not_owner();
+        let initial_storage_usage = env::storage_usage();
+        let mut staged = StagedCode::load();
+        staged.chunks.clear();
+        staged.save();
+        let released = Balance::from(initial_storage_usage.saturating_sub(env::storage_usage()))
+            * env::storage_byte_cost();
+        if released > 0 {
+            Promise::new(self.owner_id.clone()).transfer(released);
+        }
+    }
+
+    /// Deploys the code uploaded with `stage_code` and migrates the state, same as `upgrade()`.
+    pub fn deploy_staged_code(&mut self) -> Promise {
+        self.abort_if_not_owner_or_role(Role::Upgrader);
+        let mut staged = StagedCode::load();
+        let code = staged
+            .code()
+            .unwrap_or_else(|| ContractError::NoStagedCode.panic());
+        staged.chunks.clear();
+        staged.save();
+        let mut registry = CodeRegistry::load();
+        registry.stage_deployment(&code);
+        registry.save();
+
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call(
+                "migrate".to_string(),
+                vec![],
+                0,
+                remaining_gas(DEPLOY_GAS_LEFTOVER),
+            )
+    }
+
+    /// Reverse migration hook, called by `rollback()` right before the previous code is
+    /// deployed. Doesn't take `self` so the bindgen wrapper doesn't write the current layout back.
+    #[private]
+    pub fn downgrade_state(state_version: StateVersion) {
+        let contract: Contract = env::state_read().expect("Contract is not initialized.");
+        migration::downgrade_state(contract, state_version);
+    }
+}
+
+impl Contract {
+    /// Appends a chunk to the staged code. The storage is paid from the attached deposit and the
+    /// rest of the deposit is refunded.
+    pub(crate) fn internal_stage_code(&mut self, chunk: Vec<u8>) {
+        if chunk.is_empty() {
+            ContractError::EmptyCode.panic();
+        }
+        let initial_storage_usage = env::storage_usage();
+        let mut staged = StagedCode::load();
+        staged.chunks.push(&chunk);
+        staged.save();
+        self.internal_settle_storage(initial_storage_usage);
+    }
+}
+
+/// Uploads a chunk of the next wasm binary, taken as the raw input like in `upgrade()`.
+/// Big binaries can be uploaded in several transactions, the chunks are concatenated in order.
+/// Review `staged_code_hash` before calling `deploy_staged_code`.
+#[no_mangle]
+pub fn stage_code() {
+    env::setup_panic_hook();
+
+    let mut contract: Contract = env::state_read().expect("Contract is not initialized");
+    contract.abort_if_not_owner();
+    let chunk = env::input().unwrap_or_default();
+    contract.internal_stage_code(chunk);
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct DowngradeArgs {
+    state_version: StateVersion,
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils;
+
+    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
+        let mut builder = test_utils::get_context(predecessor_account_id);
+        builder.prepaid_gas(Gas(300_000_000_000_000));
+        builder
+    }
+
+    fn deploy(code: &[u8]) {
+        let mut registry = CodeRegistry::load();
+        registry.stage_deployment(code);
+        registry.record_deployment();
+        registry.save();
+    }
+
+    #[test]
+    fn test_code_history() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        deploy(b"v2");
+        deploy(b"v3");
+
+        let history = contract.code_history();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].code_hash, None);
+        assert_eq!(history[2].code_hash, Some(env::sha256_array(b"v3").into()));
+        assert_eq!(history[2].state_version, StateVersion::CURRENT);
+
+        deploy(b"v4");
+        let registry = CodeRegistry::load();
+        assert!(!registry.blobs.contains_key(&env::sha256_array(b"v2")));
+        assert!(registry.blobs.contains_key(&env::sha256_array(b"v3")));
+        assert!(registry.blobs.contains_key(&env::sha256_array(b"v4")));
+    }
+
+    #[test]
+    fn test_rollback_by_owner() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        deploy(b"v2");
+        deploy(b"v3");
+        contract.rollback();
+        assert_eq!(
+            CodeRegistry::load().pending_code_hash,
+            Some(env::sha256_array(b"v2"))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The previous code is not stored")]
+    fn test_rollback_to_unknown_code() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        deploy(b"v2");
+        contract.rollback();
+    }
+
+    #[test]
+    fn test_rollback_by_guardian_quorum() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.extend_guardians(vec![accounts(2), accounts(3), accounts(4)]);
+        deploy(b"v2");
+        deploy(b"v3");
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.approve_rollback();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.approve_rollback();
+        assert_eq!(contract.rollback_approvals().len(), 2);
+        contract.rollback();
+        assert!(contract.rollback_approvals().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough guardian approvals for rollback")]
+    fn test_rollback_by_guardian_without_quorum() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.extend_guardians(vec![accounts(2), accounts(3), accounts(4)]);
+        deploy(b"v2");
+        deploy(b"v3");
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.approve_rollback();
+        contract.rollback();
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough guardian approvals for rollback")]
+    fn test_rollback_approval_of_removed_guardian() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.extend_guardians(vec![accounts(2), accounts(3), accounts(4)]);
+        deploy(b"v2");
+        deploy(b"v3");
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.approve_rollback();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.approve_rollback();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.remove_guardians(vec![accounts(3)]);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.rollback();
+    }
+
+    #[test]
+    fn test_stage_and_deploy_code() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        assert_eq!(contract.staged_code_hash(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(near_sdk::ONE_NEAR)
+            .build());
+        contract.internal_stage_code(b"wasm ".to_vec());
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.internal_stage_code(b"code".to_vec());
+        assert_eq!(
+            contract.staged_code_hash(),
+            Some(env::sha256_array(b"wasm code").into())
+        );
+
+        contract.deploy_staged_code();
+        assert_eq!(contract.staged_code_hash(), None);
+        assert_eq!(
+            CodeRegistry::load().pending_code_hash,
+            Some(env::sha256_array(b"wasm code"))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The attached deposit is less than the storage cost")]
+    fn test_stage_code_without_deposit() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.internal_stage_code(b"wasm code".to_vec());
+    }
+
+    #[test]
+    #[should_panic(expected = "There is no staged code")]
+    fn test_deploy_without_staged_code() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.deploy_staged_code();
+    }
+
+    #[test]
+    fn test_deploy_staged_code_by_upgrader() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(near_sdk::ONE_NEAR).build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.internal_stage_code(b"wasm code".to_vec());
+        contract.grant_role(accounts(2), Role::Upgrader);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(0)
+            .build());
+        contract.deploy_staged_code();
+        assert_eq!(
+            CodeRegistry::load().pending_code_hash,
+            Some(env::sha256_array(b"wasm code"))
+        );
+    }
+
+    #[test]
+    fn test_stage_code_keeps_registry_layout() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(near_sdk::ONE_NEAR).build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        deploy(b"v2");
+        let registry = env::storage_read(CODE_REGISTRY_KEY);
+
+        contract.internal_stage_code(b"wasm code".to_vec());
+        assert_eq!(env::storage_read(CODE_REGISTRY_KEY), registry);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ENOUGH_GAS (516)")]
+    fn test_deploy_staged_code_without_gas() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(near_sdk::ONE_NEAR).build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.internal_stage_code(b"wasm code".to_vec());
+
+        testing_env!(context
+            .attached_deposit(0)
+            .prepaid_gas(DEPLOY_GAS_LEFTOVER)
+            .build());
+        contract.deploy_staged_code();
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INVALID_CODE_REGISTRY (515)")]
+    fn test_invalid_code_registry() {
+        testing_env!(get_context(accounts(1)).build());
+        env::storage_write(CODE_REGISTRY_KEY, b"garbage");
+        CodeRegistry::load();
+    }
+
+    #[test]
+    fn test_downgrade_state() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        env::state_write(&contract);
+
+        Contract::downgrade_state(StateVersion::V1);
+        assert_eq!(StateVersion::read(), StateVersion::V1);
+        let v1: migration::ContractV1 = env::state_read().unwrap();
+        assert_eq!(v1.owner_id, accounts(1));
+        assert_eq!(v1.token.total_supply, 1_000);
+    }
+}
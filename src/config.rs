@@ -0,0 +1,284 @@
+use crate::*;
+
+/// Metadata of the currencies Tether deploys this contract for.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum MetadataPreset {
+    Usdt,
+    Xaut,
+    Eurt,
+}
+
+impl MetadataPreset {
+    pub fn metadata(self) -> FungibleTokenMetadata {
+        let (name, symbol, icon) = match self {
+            MetadataPreset::Usdt => ("Tether USD", "USDt", Some(DATA_IMAGE_SVG_NEAR_ICON)),
+            MetadataPreset::Xaut => ("Tether Gold", "XAUt", None),
+            MetadataPreset::Eurt => ("Euro Tether", "EURt", None),
+        };
+        FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.to_string(),
+            name: name.to_string(),
+            symbol: symbol.to_string(),
+            icon: icon.map(str::to_string),
+            reference: None,
+            reference_hash: None,
+            decimals: 6,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum MetadataConfig {
+    Preset(MetadataPreset),
+    Custom(FungibleTokenMetadata),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RoleGrant {
+    pub account_id: AccountId,
+    pub role: Role,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Distribution {
+    pub account_id: AccountId,
+    pub amount: U128,
+}
+
+/// Everything `new_with_config` needs to set up a token instance.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InitConfig {
+    pub owner_id: AccountId,
+    pub metadata: MetadataConfig,
+    #[serde(default)]
+    pub guardians: Vec<AccountId>,
+    #[serde(default)]
+    pub roles: Vec<RoleGrant>,
+    #[serde(default)]
+    pub supply_cap: Option<U128>,
+    /// Initial supply, minted to the given accounts.
+    #[serde(default)]
+    pub distribution: Vec<Distribution>,
+    #[serde(default)]
+    pub blacklist_mode: BlackListMode,
+}
+
+impl InitConfig {
+    /// The only place the init config is validated. Metadata is validated when it is stored.
+    pub fn validate(&self) {
+        let mut guardians = self.guardians.clone();
+        guardians.sort();
+        guardians.dedup();
+        if guardians.len() != self.guardians.len() {
+            ContractError::InvalidConfig.panic_with("duplicate guardians");
+        }
+
+        let mut total: Balance = 0;
+        for distribution in &self.distribution {
+            if distribution.amount.0 == 0 {
+                ContractError::InvalidConfig
+                    .panic_with(&format!("zero distribution to {}", distribution.account_id));
+            }
+            total = total
+                .checked_add(distribution.amount.0)
+                .unwrap_or_else(|| ContractError::InvalidConfig.panic_with("supply overflow"));
+        }
+        if let Some(cap) = self.supply_cap {
+            if total > cap.0 {
+                ContractError::InvalidConfig
+                    .panic_with("the initial distribution exceeds the supply cap");
+            }
+        }
+    }
+}
+
+impl Contract {
+    /// Mints new tokens, respecting the supply cap.
+    pub(crate) fn internal_mint(
+        &mut self,
+        account_id: &AccountId,
+        amount: Balance,
+        memo: Option<&str>,
+    ) {
+        if let Some(cap) = self.supply_cap {
+            if self.token.total_supply.saturating_add(amount) > cap {
+                ContractError::SupplyCapExceeded.panic();
+            }
+        }
+        self.internal_checkpoint_balance(account_id);
+        self.internal_checkpoint_supply();
+        self.token.internal_deposit(account_id, amount);
+        self.internal_sync_holder(account_id);
+        event::emit::ft_mint(account_id, amount, memo);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    #[init]
+    pub fn new_with_config(config: InitConfig) -> Self {
+        config.validate();
+        let metadata = match config.metadata {
+            MetadataConfig::Preset(preset) => preset.metadata(),
+            MetadataConfig::Custom(metadata) => metadata,
+        };
+        let owner_id = config.owner_id;
+        let mut this = Self {
+            owner_id: owner_id.clone(),
+            proposed_owner_id: owner_id.clone(),
+            token: FungibleToken::new(b"a".to_vec()),
+            guardians: UnorderedSet::new(b"c".to_vec()),
+            metadata: LazyOption::new(b"m".to_vec(), None),
+            black_list: LookupMap::new(b"b".to_vec()),
+            status: ContractStatus::Working,
+            migration: None,
+            metadata_version: 0,
+            roles: UnorderedMap::new(b"o".to_vec()),
+            supply_cap: config.supply_cap.map(|cap| cap.0),
+            blacklist_mode: config.blacklist_mode,
+            snapshots: Snapshots::new(),
+            holders: UnorderedSet::new(b"d".to_vec()),
+            transfer_fee: None,
+            fee_exemptions: UnorderedSet::new(b"f".to_vec()),
+            limits: TransferLimits::new(),
+            memo_policies: LookupMap::new(b"e".to_vec()),
+            incoming_policies: LookupMap::new(b"i".to_vec()),
+            recoveries: Vector::new(b"g".to_vec()),
+            sponsorship: SponsoredStorage::new(),
+            force_close_allowed: true,
+            audit: None,
+            bridge: Bridge::new(),
+        };
+        this.internal_set_metadata(metadata);
+        StateVersion::CURRENT.write();
+        let mut registry = CodeRegistry::load();
+        registry.record_deployment();
+        registry.save();
+
+        this.guardians.extend(config.guardians);
+        for grant in config.roles {
+            this.internal_grant_role(&grant.account_id, grant.role);
+        }
+        this.token.internal_register_account(&owner_id);
+        for distribution in config.distribution {
+            if !this.token.accounts.contains_key(&distribution.account_id) {
+                this.token
+                    .internal_register_account(&distribution.account_id);
+            }
+            this.internal_mint(
+                &distribution.account_id,
+                distribution.amount.0,
+                Some("Initial supply"),
+            );
+        }
+        this
+    }
+
+    /// `None` removes the cap. The cap can't be set below the current total supply.
+    pub fn set_supply_cap(&mut self, supply_cap: Option<U128>) {
+        self.abort_if_not_owner();
+        if let Some(cap) = supply_cap {
+            if cap.0 < self.token.total_supply {
+                ContractError::SupplyCapExceeded.panic();
+            }
+        }
+        self.supply_cap = supply_cap.map(|cap| cap.0);
+    }
+
+    pub fn supply_cap(&self) -> Option<U128> {
+        self.supply_cap.map(U128)
+    }
+
+    pub fn set_blacklist_mode(&mut self, mode: BlackListMode) {
+        self.abort_if_not_owner();
+        self.blacklist_mode = mode;
+    }
+
+    pub fn blacklist_mode(&self) -> BlackListMode {
+        self.blacklist_mode
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::get_context;
+
+    fn config() -> InitConfig {
+        InitConfig {
+            owner_id: accounts(1),
+            metadata: MetadataConfig::Preset(MetadataPreset::Xaut),
+            guardians: vec![accounts(2)],
+            roles: vec![RoleGrant {
+                account_id: accounts(3),
+                role: Role::Minter,
+            }],
+            supply_cap: Some(1_000.into()),
+            distribution: vec![
+                Distribution {
+                    account_id: accounts(1),
+                    amount: 600.into(),
+                },
+                Distribution {
+                    account_id: accounts(4),
+                    amount: 300.into(),
+                },
+            ],
+            blacklist_mode: BlackListMode::SenderAndReceiver,
+        }
+    }
+
+    #[test]
+    fn test_new_with_config() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = Contract::new_with_config(config());
+        assert_eq!(contract.ft_metadata().symbol, "XAUt");
+        assert_eq!(contract.guardians(), vec![accounts(2)]);
+        assert_eq!(contract.roles_of(accounts(3)), vec![Role::Minter]);
+        assert_eq!(contract.supply_cap(), Some(1_000.into()));
+        assert_eq!(contract.blacklist_mode(), BlackListMode::SenderAndReceiver);
+        assert_eq!(contract.ft_total_supply().0, 900);
+        assert_eq!(contract.ft_balance_of(accounts(4)).0, 300);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "ERR_INVALID_CONFIG (600): Invalid init config: the initial distribution exceeds the supply cap"
+    )]
+    fn test_new_with_config_over_cap() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut config = config();
+        config.supply_cap = Some(500.into());
+        Contract::new_with_config(config);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_SUPPLY_CAP_EXCEEDED")]
+    fn test_mint_over_cap() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_with_config(config());
+        contract.mint(&accounts(4), 101.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BANNED (300): Account is banned: danny")]
+    fn test_blacklisted_receiver() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_with_config(config());
+        contract.add_to_blacklist(&accounts(3));
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(3), 10.into(), None);
+    }
+}
@@ -0,0 +1,363 @@
+use crate::*;
+
+/// Errors the contract aborts with. The panic message has the stable form
+/// `ERR_NAME (code): message`, optionally followed by `: details`, so integrations can match
+/// on the name or the code instead of the text. Codes must never be reused or renumbered.
+///
+/// Panics raised inside `near_contract_standards` (e.g. insufficient balance) keep their
+/// upstream messages.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ContractError {
+    // Access control.
+    NotOwner = 100,
+    NotOwnerOrGuardian = 101,
+    NotGuardian = 102,
+    NotProposedOwner = 103,
+    OwnershipNotProposed = 104,
+    MissingRole = 105,
+
+    // Contract status.
+    Paused = 200,
+    Migrating = 201,
+    NotWorking = 202,
+    NotPaused = 203,
+
+    // Accounts and guardians.
+    Banned = 300,
+    NotBanned = 301,
+    GuardianExists = 302,
+    GuardianNotFound = 303,
+    InsufficientDeposit = 304,
+    SupplyCapExceeded = 305,
+    ForceCloseDisabled = 306,
+    PositiveBalance = 307,
+
+    // Metadata.
+    MetadataNotSet = 400,
+    InvalidIcon = 401,
+    DecimalsChange = 402,
+
+    // Migrations and upgrades.
+    UnknownStateVersion = 500,
+    MigrationInProgress = 501,
+    NoMigration = 502,
+    MigrationQueueNotEmpty = 503,
+    NoPreviousCode = 510,
+    PreviousCodeNotStored = 511,
+    NotEnoughApprovals = 512,
+    NoStagedCode = 513,
+    EmptyCode = 514,
+    InvalidCodeRegistry = 515,
+    NotEnoughGas = 516,
+
+    // Configuration.
+    InvalidConfig = 600,
+
+    // Snapshots.
+    UnknownSnapshot = 700,
+
+    // Fees.
+    InvalidFee = 800,
+    FeeCollectorNotRegistered = 801,
+
+    // Transfer limits.
+    TransferLimitExceeded = 900,
+    WindowLimitExceeded = 901,
+    CircuitBreakerTripped = 902,
+    GlobalLimitExceeded = 903,
+
+    // Memos.
+    MemoRequired = 1000,
+    InvalidMemo = 1001,
+
+    // Receiver policies.
+    ReceiverRequiresCall = 1100,
+    ReceiverRejectsTransfers = 1101,
+
+    // Sponsored storage.
+    InsufficientSponsorPool = 1200,
+    SponsorLimitExceeded = 1201,
+
+    // Batch views.
+    BatchTooLarge = 1300,
+
+    // Ledger audits.
+    AuditOutOfOrder = 1400,
+    AuditInterrupted = 1401,
+
+    // Bridge.
+    UnknownBridgeChain = 1500,
+    BridgeChainPaused = 1501,
+    BridgeTxProcessed = 1502,
+    BridgeCapExceeded = 1503,
+    BridgeOutstandingExceeded = 1504,
+    InvalidBridgeRequest = 1505,
+}
+
+impl ContractError {
+    pub const ALL: &'static [ContractError] = &[
+        ContractError::NotOwner,
+        ContractError::NotOwnerOrGuardian,
+        ContractError::NotGuardian,
+        ContractError::NotProposedOwner,
+        ContractError::OwnershipNotProposed,
+        ContractError::MissingRole,
+        ContractError::Paused,
+        ContractError::Migrating,
+        ContractError::NotWorking,
+        ContractError::NotPaused,
+        ContractError::Banned,
+        ContractError::NotBanned,
+        ContractError::GuardianExists,
+        ContractError::GuardianNotFound,
+        ContractError::InsufficientDeposit,
+        ContractError::SupplyCapExceeded,
+        ContractError::ForceCloseDisabled,
+        ContractError::PositiveBalance,
+        ContractError::MetadataNotSet,
+        ContractError::InvalidIcon,
+        ContractError::DecimalsChange,
+        ContractError::UnknownStateVersion,
+        ContractError::MigrationInProgress,
+        ContractError::NoMigration,
+        ContractError::MigrationQueueNotEmpty,
+        ContractError::NoPreviousCode,
+        ContractError::PreviousCodeNotStored,
+        ContractError::NotEnoughApprovals,
+        ContractError::NoStagedCode,
+        ContractError::EmptyCode,
+        ContractError::InvalidCodeRegistry,
+        ContractError::NotEnoughGas,
+        ContractError::InvalidConfig,
+        ContractError::UnknownSnapshot,
+        ContractError::InvalidFee,
+        ContractError::FeeCollectorNotRegistered,
+        ContractError::TransferLimitExceeded,
+        ContractError::WindowLimitExceeded,
+        ContractError::CircuitBreakerTripped,
+        ContractError::GlobalLimitExceeded,
+        ContractError::MemoRequired,
+        ContractError::InvalidMemo,
+        ContractError::ReceiverRequiresCall,
+        ContractError::ReceiverRejectsTransfers,
+        ContractError::InsufficientSponsorPool,
+        ContractError::SponsorLimitExceeded,
+        ContractError::BatchTooLarge,
+        ContractError::AuditOutOfOrder,
+        ContractError::AuditInterrupted,
+        ContractError::UnknownBridgeChain,
+        ContractError::BridgeChainPaused,
+        ContractError::BridgeTxProcessed,
+        ContractError::BridgeCapExceeded,
+        ContractError::BridgeOutstandingExceeded,
+        ContractError::InvalidBridgeRequest,
+    ];
+
+    pub fn code(self) -> u16 {
+        self as u16
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ContractError::NotOwner => "ERR_NOT_OWNER",
+            ContractError::NotOwnerOrGuardian => "ERR_NOT_OWNER_OR_GUARDIAN",
+            ContractError::NotGuardian => "ERR_NOT_GUARDIAN",
+            ContractError::NotProposedOwner => "ERR_NOT_PROPOSED_OWNER",
+            ContractError::OwnershipNotProposed => "ERR_OWNERSHIP_NOT_PROPOSED",
+            ContractError::MissingRole => "ERR_MISSING_ROLE",
+            ContractError::Paused => "ERR_PAUSED",
+            ContractError::Migrating => "ERR_MIGRATING",
+            ContractError::NotWorking => "ERR_NOT_WORKING",
+            ContractError::NotPaused => "ERR_NOT_PAUSED",
+            ContractError::Banned => "ERR_BANNED",
+            ContractError::NotBanned => "ERR_NOT_BANNED",
+            ContractError::GuardianExists => "ERR_GUARDIAN_EXISTS",
+            ContractError::GuardianNotFound => "ERR_GUARDIAN_NOT_FOUND",
+            ContractError::InsufficientDeposit => "ERR_INSUFFICIENT_DEPOSIT",
+            ContractError::SupplyCapExceeded => "ERR_SUPPLY_CAP_EXCEEDED",
+            ContractError::ForceCloseDisabled => "ERR_FORCE_CLOSE_DISABLED",
+            ContractError::PositiveBalance => "ERR_POSITIVE_BALANCE",
+            ContractError::MetadataNotSet => "ERR_METADATA_NOT_SET",
+            ContractError::InvalidIcon => "ERR_INVALID_ICON",
+            ContractError::DecimalsChange => "ERR_DECIMALS_CHANGE",
+            ContractError::UnknownStateVersion => "ERR_UNKNOWN_STATE_VERSION",
+            ContractError::MigrationInProgress => "ERR_MIGRATION_IN_PROGRESS",
+            ContractError::NoMigration => "ERR_NO_MIGRATION",
+            ContractError::MigrationQueueNotEmpty => "ERR_MIGRATION_QUEUE_NOT_EMPTY",
+            ContractError::NoPreviousCode => "ERR_NO_PREVIOUS_CODE",
+            ContractError::PreviousCodeNotStored => "ERR_PREVIOUS_CODE_NOT_STORED",
+            ContractError::NotEnoughApprovals => "ERR_NOT_ENOUGH_APPROVALS",
+            ContractError::NoStagedCode => "ERR_NO_STAGED_CODE",
+            ContractError::EmptyCode => "ERR_EMPTY_CODE",
+            ContractError::InvalidCodeRegistry => "ERR_INVALID_CODE_REGISTRY",
+            ContractError::NotEnoughGas => "ERR_NOT_ENOUGH_GAS",
+            ContractError::InvalidConfig => "ERR_INVALID_CONFIG",
+            ContractError::UnknownSnapshot => "ERR_UNKNOWN_SNAPSHOT",
+            ContractError::InvalidFee => "ERR_INVALID_FEE",
+            ContractError::FeeCollectorNotRegistered => "ERR_FEE_COLLECTOR_NOT_REGISTERED",
+            ContractError::TransferLimitExceeded => "ERR_TRANSFER_LIMIT_EXCEEDED",
+            ContractError::WindowLimitExceeded => "ERR_WINDOW_LIMIT_EXCEEDED",
+            ContractError::CircuitBreakerTripped => "ERR_CIRCUIT_BREAKER_TRIPPED",
+            ContractError::GlobalLimitExceeded => "ERR_GLOBAL_LIMIT_EXCEEDED",
+            ContractError::MemoRequired => "ERR_MEMO_REQUIRED",
+            ContractError::InvalidMemo => "ERR_INVALID_MEMO",
+            ContractError::ReceiverRequiresCall => "ERR_RECEIVER_REQUIRES_CALL",
+            ContractError::ReceiverRejectsTransfers => "ERR_RECEIVER_REJECTS_TRANSFERS",
+            ContractError::InsufficientSponsorPool => "ERR_INSUFFICIENT_SPONSOR_POOL",
+            ContractError::SponsorLimitExceeded => "ERR_SPONSOR_LIMIT_EXCEEDED",
+            ContractError::BatchTooLarge => "ERR_BATCH_TOO_LARGE",
+            ContractError::AuditOutOfOrder => "ERR_AUDIT_OUT_OF_ORDER",
+            ContractError::AuditInterrupted => "ERR_AUDIT_INTERRUPTED",
+            ContractError::UnknownBridgeChain => "ERR_UNKNOWN_BRIDGE_CHAIN",
+            ContractError::BridgeChainPaused => "ERR_BRIDGE_CHAIN_PAUSED",
+            ContractError::BridgeTxProcessed => "ERR_BRIDGE_TX_PROCESSED",
+            ContractError::BridgeCapExceeded => "ERR_BRIDGE_CAP_EXCEEDED",
+            ContractError::BridgeOutstandingExceeded => "ERR_BRIDGE_OUTSTANDING_EXCEEDED",
+            ContractError::InvalidBridgeRequest => "ERR_INVALID_BRIDGE_REQUEST",
+        }
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            ContractError::NotOwner => "This method might be called only by owner account",
+            ContractError::NotOwnerOrGuardian => {
+                "This method can be called only by owner or guardian"
+            }
+            ContractError::NotGuardian => "This method can be called only by guardian",
+            ContractError::NotProposedOwner => {
+                "This method can be called only by the proposed owner"
+            }
+            ContractError::OwnershipNotProposed => "There is no proposed owner",
+            ContractError::MissingRole => "This method requires a role",
+            ContractError::Paused => "Operation aborted because the contract under maintenance",
+            ContractError::Migrating => {
+                "Operation aborted because the contract state is being migrated"
+            }
+            ContractError::NotWorking => "The contract is not working",
+            ContractError::NotPaused => "The contract is not paused",
+            ContractError::Banned => "Account is banned",
+            ContractError::NotBanned => "Account is not banned",
+            ContractError::GuardianExists => "The guardian already exists",
+            ContractError::GuardianNotFound => "The guardian doesn't exist",
+            ContractError::InsufficientDeposit => {
+                "The attached deposit is less than the storage cost"
+            }
+            ContractError::SupplyCapExceeded => "The supply cap is exceeded",
+            ContractError::ForceCloseDisabled => "Accounts with a balance can't be force closed",
+            ContractError::PositiveBalance => {
+                "Can't unregister the account with the positive balance without force"
+            }
+            ContractError::MetadataNotSet => "The metadata is not set",
+            ContractError::InvalidIcon => "The icon has to be an image data URL",
+            ContractError::DecimalsChange => "Decimals can't be changed",
+            ContractError::UnknownStateVersion => "Unknown contract state version",
+            ContractError::MigrationInProgress => "Another migration is in progress",
+            ContractError::NoMigration => "There is no migration in progress",
+            ContractError::MigrationQueueNotEmpty => "Migration queue is not empty",
+            ContractError::NoPreviousCode => "There is no previous code to roll back to",
+            ContractError::PreviousCodeNotStored => "The previous code is not stored",
+            ContractError::NotEnoughApprovals => "Not enough guardian approvals for rollback",
+            ContractError::NoStagedCode => "There is no staged code",
+            ContractError::EmptyCode => "Expected wasm code in the input",
+            ContractError::InvalidCodeRegistry => "Unable to read code registry",
+            ContractError::NotEnoughGas => "Not enough gas attached to deploy and migrate",
+            ContractError::InvalidConfig => "Invalid init config",
+            ContractError::UnknownSnapshot => "The snapshot doesn't exist",
+            ContractError::InvalidFee => "The fee can't exceed 10000 basis points",
+            ContractError::FeeCollectorNotRegistered => "The fee collector is not registered",
+            ContractError::TransferLimitExceeded => "The amount exceeds the per-transfer limit",
+            ContractError::WindowLimitExceeded => "The amount exceeds the 24 hour limit",
+            ContractError::CircuitBreakerTripped => {
+                "Transfers are stopped by the volume circuit breaker"
+            }
+            ContractError::GlobalLimitExceeded => {
+                "The amount exceeds the remaining volume of the window"
+            }
+            ContractError::MemoRequired => "A memo is required by the receiver",
+            ContractError::InvalidMemo => "The memo doesn't match the format of the receiver",
+            ContractError::ReceiverRequiresCall => "The receiver only accepts ft_transfer_call",
+            ContractError::ReceiverRejectsTransfers => "The receiver doesn't accept transfers",
+            ContractError::InsufficientSponsorPool => {
+                "The sponsor pool can't cover the storage of the receiver"
+            }
+            ContractError::SponsorLimitExceeded => {
+                "The sender reached the daily limit of registrations paid by the global pool"
+            }
+            ContractError::BatchTooLarge => "Too many accounts in one call",
+            ContractError::AuditOutOfOrder => {
+                "The audit has to continue at the index returned by the previous step"
+            }
+            ContractError::AuditInterrupted => {
+                "Balances changed during the audit, it has to be restarted from index 0"
+            }
+            ContractError::UnknownBridgeChain => "The chain is not configured for the bridge",
+            ContractError::BridgeChainPaused => "The bridge to this chain is paused",
+            ContractError::BridgeTxProcessed => "The source transaction was already minted",
+            ContractError::BridgeCapExceeded => "The mint exceeds the cap of the source chain",
+            ContractError::BridgeOutstandingExceeded => {
+                "The burn exceeds the amount minted from the destination chain"
+            }
+            ContractError::InvalidBridgeRequest => "Invalid amount, address or transaction hash",
+        }
+    }
+
+    pub fn panic(self) -> ! {
+        env::panic_str(&self.to_string())
+    }
+
+    pub fn panic_with(self, details: &str) -> ! {
+        env::panic_str(&format!("{}: {}", self, details))
+    }
+}
+
+impl std::fmt::Display for ContractError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}): {}", self.name(), self.code(), self.message())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ErrorCodeView {
+    pub code: u16,
+    pub name: String,
+    pub message: String,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// All errors the contract can abort with.
+    pub fn error_codes(&self) -> Vec<ErrorCodeView> {
+        ContractError::ALL
+            .iter()
+            .map(|error| ErrorCodeView {
+                code: error.code(),
+                name: error.name().to_string(),
+                message: error.message().to_string(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_error_codes_are_unique() {
+        let codes: HashSet<u16> = ContractError::ALL.iter().map(|e| e.code()).collect();
+        let names: HashSet<&str> = ContractError::ALL.iter().map(|e| e.name()).collect();
+        assert_eq!(codes.len(), ContractError::ALL.len());
+        assert_eq!(names.len(), ContractError::ALL.len());
+    }
+
+    #[test]
+    fn test_error_format() {
+        assert_eq!(
+            ContractError::NotOwner.to_string(),
+            "ERR_NOT_OWNER (100): This method might be called only by owner account"
+        );
+    }
+}
@@ -1,7 +1,242 @@
-pub mod emit {
+//! Events logged by the contract, in the NEP-297 format. The types below describe every event
+//! the contract emits, so indexers can depend on this module and read logs with
+//! [`parse_event`] instead of matching JSON by hand.
+
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{log, serde_json, AccountId};
+
+const EVENT_JSON_PREFIX: &str = "EVENT_JSON:";
+
+/// NEP-297 standard name of the events that are specific to this contract. Token movements are
+/// reported with the `nep141` events from `near_contract_standards`.
+pub const STANDARD: &str = "tether_token";
+/// Version of the `tether_token` events. Bumped whenever the data of an event changes.
+pub const VERSION: &str = "1.0.0";
+/// Version of the `nep141` events.
+pub const NEP141_VERSION: &str = "1.0.0";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtMint {
+    pub owner_id: AccountId,
+    pub amount: U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtTransfer {
+    pub old_owner_id: AccountId,
+    pub new_owner_id: AccountId,
+    pub amount: U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtBurn {
+    pub owner_id: AccountId,
+    pub amount: U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MetadataUpdate {
+    pub metadata_version: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Snapshot {
+    pub snapshot_id: U64,
+    pub block_height: U64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CircuitBreakerTripped {
+    pub volume: U128,
+    /// First block of the window in which the breaker tripped.
+    pub window_start: U64,
+}
+
+/// A transfer rejected by a limit of the sender. `limit` is `per_transfer` or `per_window`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferLimitExceeded {
+    pub account_id: AccountId,
+    pub amount: U128,
+    pub limit: String,
+    pub max: U128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokensRecovered {
+    pub token_id: AccountId,
+    pub amount: U128,
+    pub receiver_id: AccountId,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AuditResult {
+    /// Block in which the audit completed.
+    pub block_height: U64,
+    pub holder_count: U64,
+    /// Sum of the holder balances.
+    pub holders_balance: U128,
+    pub total_supply: U128,
+    pub consistent: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BridgeMint {
+    pub recipient: AccountId,
+    pub amount: U128,
+    pub source_chain: String,
+    pub source_tx_hash: String,
+}
+
+/// Request to release `amount` to `destination_address`. `nonce` increases with every burn, so
+/// relayers can release each burn exactly once.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BridgeBurn {
+    pub sender_id: AccountId,
+    pub amount: U128,
+    pub destination_chain: String,
+    pub destination_address: String,
+    pub nonce: U64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
+pub enum Nep141EventKind {
+    FtMint(Vec<FtMint>),
+    FtTransfer(Vec<FtTransfer>),
+    FtBurn(Vec<FtBurn>),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum TetherTokenEventKind {
+    MetadataUpdate(Vec<MetadataUpdate>),
+    Snapshot(Vec<Snapshot>),
+    CircuitBreakerTripped(Vec<CircuitBreakerTripped>),
+    TransferLimitExceeded(Vec<TransferLimitExceeded>),
+    TokensRecovered(Vec<TokensRecovered>),
+    AuditResult(Vec<AuditResult>),
+    BridgeMint(Vec<BridgeMint>),
+    BridgeBurn(Vec<BridgeBurn>),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Nep141Event {
+    pub version: String,
+    #[serde(flatten)]
+    pub kind: Nep141EventKind,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TetherTokenEvent {
+    pub version: String,
+    #[serde(flatten)]
+    pub kind: TetherTokenEventKind,
+}
+
+/// An event logged by the contract, keyed by its NEP-297 standard.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "standard", rename_all = "snake_case")]
+pub enum ContractEvent {
+    Nep141(Nep141Event),
+    TetherToken(TetherTokenEvent),
+}
+
+impl ContractEvent {
+    fn tether_token(kind: TetherTokenEventKind) -> Self {
+        ContractEvent::TetherToken(TetherTokenEvent {
+            version: VERSION.to_string(),
+            kind,
+        })
+    }
+
+    /// The log line of the event, as written by the contract.
+    pub fn to_log(&self) -> String {
+        format!(
+            "{}{}",
+            EVENT_JSON_PREFIX,
+            serde_json::to_string(self).expect("Unable to serialize event")
+        )
+    }
+
+    fn emit(&self) {
+        log!("{}", self.to_log());
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventParseError {
+    /// The log line doesn't start with `EVENT_JSON:`.
+    NotAnEvent,
+    /// The standard, the event name or the data don't match any event of the contract.
+    InvalidEvent(String),
+    UnsupportedVersion {
+        standard: String,
+        version: String,
+    },
+}
+
+impl std::fmt::Display for EventParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventParseError::NotAnEvent => write!(f, "not an event log"),
+            EventParseError::InvalidEvent(err) => write!(f, "invalid event: {}", err),
+            EventParseError::UnsupportedVersion { standard, version } => {
+                write!(f, "unsupported {} version {}", standard, version)
+            }
+        }
+    }
+}
+
+/// Parses a log line of the contract. Other logs are rejected with `NotAnEvent`, events of a
+/// version this module doesn't know with `UnsupportedVersion`.
+pub fn parse_event(log: &str) -> Result<ContractEvent, EventParseError> {
+    let json = log
+        .strip_prefix(EVENT_JSON_PREFIX)
+        .ok_or(EventParseError::NotAnEvent)?;
+    let event: ContractEvent =
+        serde_json::from_str(json).map_err(|err| EventParseError::InvalidEvent(err.to_string()))?;
+    let (standard, version, supported) = match &event {
+        ContractEvent::Nep141(event) => ("nep141", &event.version, NEP141_VERSION),
+        ContractEvent::TetherToken(event) => (STANDARD, &event.version, VERSION),
+    };
+    if version != supported {
+        return Err(EventParseError::UnsupportedVersion {
+            standard: standard.to_string(),
+            version: version.clone(),
+        });
+    }
+    Ok(event)
+}
+
+pub(crate) mod emit {
     use near_contract_standards::fungible_token::events::{FtBurn, FtMint};
+    use near_sdk::Balance;
 
-    use crate::*;
+    use super::*;
 
     pub fn ft_mint(owner_id: &AccountId, amount: Balance, memo: Option<&str>) {
         (FtMint {
@@ -20,4 +255,233 @@ pub mod emit {
         })
         .emit();
     }
+
+    pub fn metadata_update(metadata_version: u64) {
+        ContractEvent::tether_token(TetherTokenEventKind::MetadataUpdate(vec![MetadataUpdate {
+            metadata_version,
+        }]))
+        .emit();
+    }
+
+    pub fn circuit_breaker_tripped(volume: Balance, window_start: u64) {
+        ContractEvent::tether_token(TetherTokenEventKind::CircuitBreakerTripped(vec![
+            CircuitBreakerTripped {
+                volume: volume.into(),
+                window_start: window_start.into(),
+            },
+        ]))
+        .emit();
+    }
+
+    pub fn transfer_limit_exceeded(
+        account_id: &AccountId,
+        amount: Balance,
+        limit: &str,
+        max: Balance,
+    ) {
+        ContractEvent::tether_token(TetherTokenEventKind::TransferLimitExceeded(vec![
+            TransferLimitExceeded {
+                account_id: account_id.clone(),
+                amount: amount.into(),
+                limit: limit.to_string(),
+                max: max.into(),
+            },
+        ]))
+        .emit();
+    }
+
+    pub fn tokens_recovered(token_id: &AccountId, amount: Balance, receiver_id: &AccountId) {
+        ContractEvent::tether_token(TetherTokenEventKind::TokensRecovered(vec![
+            TokensRecovered {
+                token_id: token_id.clone(),
+                amount: amount.into(),
+                receiver_id: receiver_id.clone(),
+            },
+        ]))
+        .emit();
+    }
+
+    pub fn audit_result(
+        block_height: u64,
+        holder_count: u64,
+        holders_balance: Balance,
+        total_supply: Balance,
+    ) {
+        ContractEvent::tether_token(TetherTokenEventKind::AuditResult(vec![AuditResult {
+            block_height: block_height.into(),
+            holder_count: holder_count.into(),
+            holders_balance: holders_balance.into(),
+            total_supply: total_supply.into(),
+            consistent: holders_balance == total_supply,
+        }]))
+        .emit();
+    }
+
+    pub fn bridge_mint(
+        recipient: &AccountId,
+        amount: Balance,
+        source_chain: String,
+        source_tx_hash: String,
+    ) {
+        ContractEvent::tether_token(TetherTokenEventKind::BridgeMint(vec![BridgeMint {
+            recipient: recipient.clone(),
+            amount: amount.into(),
+            source_chain,
+            source_tx_hash,
+        }]))
+        .emit();
+    }
+
+    pub fn bridge_burn(
+        sender_id: &AccountId,
+        amount: Balance,
+        destination_chain: String,
+        destination_address: String,
+        nonce: u64,
+    ) {
+        ContractEvent::tether_token(TetherTokenEventKind::BridgeBurn(vec![BridgeBurn {
+            sender_id: sender_id.clone(),
+            amount: amount.into(),
+            destination_chain,
+            destination_address,
+            nonce: nonce.into(),
+        }]))
+        .emit();
+    }
+
+    pub fn snapshot(snapshot_id: u64, block_height: u64) {
+        ContractEvent::tether_token(TetherTokenEventKind::Snapshot(vec![Snapshot {
+            snapshot_id: snapshot_id.into(),
+            block_height: block_height.into(),
+        }]))
+        .emit();
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::Contract;
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+
+    fn assert_round_trip(log: &str, expected: ContractEvent) {
+        let event = parse_event(log).unwrap();
+        assert_eq!(event, expected);
+        assert_eq!(event.to_log(), log);
+    }
+
+    #[test]
+    fn test_tether_token_events() {
+        testing_env!(VMContextBuilder::new().build());
+        emit::metadata_update(2);
+        emit::snapshot(1, 100);
+        emit::circuit_breaker_tripped(1_000, 200);
+        emit::tokens_recovered(&accounts(3), 5, &accounts(2));
+        emit::transfer_limit_exceeded(&accounts(1), 600, "per_transfer", 500);
+        emit::audit_result(42, 4, 1_300, 1_300);
+        emit::bridge_mint(
+            &accounts(2),
+            600,
+            "ethereum".to_string(),
+            "0xaa".to_string(),
+        );
+        emit::bridge_burn(
+            &accounts(2),
+            200,
+            "ethereum".to_string(),
+            "0xbb".to_string(),
+            1,
+        );
+        let logs = get_logs();
+        assert_eq!(
+            logs,
+            vec![
+                r#"EVENT_JSON:{"standard":"tether_token","version":"1.0.0","event":"metadata_update","data":[{"metadata_version":2}]}"#,
+                r#"EVENT_JSON:{"standard":"tether_token","version":"1.0.0","event":"snapshot","data":[{"snapshot_id":"1","block_height":"100"}]}"#,
+                r#"EVENT_JSON:{"standard":"tether_token","version":"1.0.0","event":"circuit_breaker_tripped","data":[{"volume":"1000","window_start":"200"}]}"#,
+                r#"EVENT_JSON:{"standard":"tether_token","version":"1.0.0","event":"tokens_recovered","data":[{"token_id":"danny","amount":"5","receiver_id":"charlie"}]}"#,
+                r#"EVENT_JSON:{"standard":"tether_token","version":"1.0.0","event":"transfer_limit_exceeded","data":[{"account_id":"bob","amount":"600","limit":"per_transfer","max":"500"}]}"#,
+                r#"EVENT_JSON:{"standard":"tether_token","version":"1.0.0","event":"audit_result","data":[{"block_height":"42","holder_count":"4","holders_balance":"1300","total_supply":"1300","consistent":true}]}"#,
+                r#"EVENT_JSON:{"standard":"tether_token","version":"1.0.0","event":"bridge_mint","data":[{"recipient":"charlie","amount":"600","source_chain":"ethereum","source_tx_hash":"0xaa"}]}"#,
+                r#"EVENT_JSON:{"standard":"tether_token","version":"1.0.0","event":"bridge_burn","data":[{"sender_id":"charlie","amount":"200","destination_chain":"ethereum","destination_address":"0xbb","nonce":"1"}]}"#,
+            ]
+        );
+        assert_round_trip(
+            &logs[3],
+            ContractEvent::tether_token(TetherTokenEventKind::TokensRecovered(vec![
+                TokensRecovered {
+                    token_id: accounts(3),
+                    amount: 5.into(),
+                    receiver_id: accounts(2),
+                },
+            ])),
+        );
+        for log in &logs {
+            assert_eq!(&parse_event(log).unwrap().to_log(), log);
+        }
+    }
+
+    #[test]
+    fn test_nep141_events() {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.token.internal_register_account(&accounts(2));
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(2), 10.into(), Some("invoice".to_string()));
+        let logs = get_logs();
+        assert_eq!(
+            logs,
+            vec![
+                r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_transfer","data":[{"old_owner_id":"bob","new_owner_id":"charlie","amount":"10","memo":"invoice"}]}"#,
+            ]
+        );
+        assert_round_trip(
+            &logs[0],
+            ContractEvent::Nep141(Nep141Event {
+                version: NEP141_VERSION.to_string(),
+                kind: Nep141EventKind::FtTransfer(vec![FtTransfer {
+                    old_owner_id: accounts(1),
+                    new_owner_id: accounts(2),
+                    amount: 10.into(),
+                    memo: Some("invoice".to_string()),
+                }]),
+            }),
+        );
+
+        emit::ft_mint(&accounts(2), 5, None);
+        emit::ft_burn(&accounts(2), 5, Some("account closed"));
+        for log in &get_logs()[1..] {
+            assert_eq!(&parse_event(log).unwrap().to_log(), log);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse_event("Closed @bob with 0"),
+            Err(EventParseError::NotAnEvent)
+        );
+        assert_eq!(
+            parse_event(
+                r#"EVENT_JSON:{"standard":"tether_token","version":"2.0.0","event":"metadata_update","data":[{"metadata_version":2}]}"#
+            ),
+            Err(EventParseError::UnsupportedVersion {
+                standard: "tether_token".to_string(),
+                version: "2.0.0".to_string(),
+            })
+        );
+        assert!(matches!(
+            parse_event(
+                r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_mint","data":[]}"#
+            ),
+            Err(EventParseError::InvalidEvent(_))
+        ));
+    }
 }
@@ -0,0 +1,188 @@
+use crate::*;
+
+const MAX_BASIS_POINTS: u16 = 10_000;
+
+/// Fee charged on `ft_transfer` and `ft_transfer_call`, like `basisPointsRate` and `maximumFee`
+/// of the Tether ERC-20 contract. The receiver gets the amount minus the fee.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferFee {
+    pub basis_points: u16,
+    pub maximum_fee: U128,
+    /// Has to be registered. Transfers from the collector are not charged.
+    pub collector_id: AccountId,
+}
+
+impl Contract {
+    fn internal_fee_for(&self, amount: Balance, sender_id: &AccountId) -> Balance {
+        let fee = match &self.transfer_fee {
+            Some(fee) => fee,
+            None => return 0,
+        };
+        if *sender_id == fee.collector_id || self.fee_exemptions.contains(sender_id) {
+            return 0;
+        }
+        // Split so that `amount * basis_points` can't overflow.
+        let basis_points = Balance::from(fee.basis_points);
+        let scale = Balance::from(MAX_BASIS_POINTS);
+        let fee_amount = amount / scale * basis_points + amount % scale * basis_points / scale;
+        std::cmp::min(fee_amount, fee.maximum_fee.0)
+    }
+
+    /// Moves the fee for a transfer of `amount` from `sender_id` to the collector and returns
+    /// what is left for the receiver.
+    pub(crate) fn internal_charge_fee(&mut self, sender_id: &AccountId, amount: U128) -> U128 {
+        let fee_amount = self.internal_fee_for(amount.0, sender_id);
+        if fee_amount == 0 {
+            return amount;
+        }
+        let collector_id = self.transfer_fee.as_ref().unwrap().collector_id.clone();
+        self.internal_checkpoint_balance(sender_id);
+        self.internal_checkpoint_balance(&collector_id);
+        self.token.internal_transfer(
+            sender_id,
+            &collector_id,
+            fee_amount,
+            Some("Transfer fee".to_string()),
+        );
+        self.internal_sync_holder(sender_id);
+        self.internal_sync_holder(&collector_id);
+        (amount.0 - fee_amount).into()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// `None` disables the fee.
+    pub fn set_transfer_fee(&mut self, fee: Option<TransferFee>) {
+        self.abort_if_not_owner();
+        if let Some(fee) = &fee {
+            if fee.basis_points > MAX_BASIS_POINTS {
+                ContractError::InvalidFee.panic_with(&fee.basis_points.to_string());
+            }
+            if !self.token.accounts.contains_key(&fee.collector_id) {
+                ContractError::FeeCollectorNotRegistered.panic_with(fee.collector_id.as_str());
+            }
+        }
+        self.transfer_fee = fee;
+    }
+
+    pub fn transfer_fee(&self) -> Option<TransferFee> {
+        self.transfer_fee.clone()
+    }
+
+    pub fn add_fee_exemptions(&mut self, account_ids: Vec<AccountId>) {
+        self.abort_if_not_owner();
+        self.fee_exemptions.extend(account_ids);
+    }
+
+    pub fn remove_fee_exemptions(&mut self, account_ids: Vec<AccountId>) {
+        self.abort_if_not_owner();
+        for account_id in account_ids {
+            self.fee_exemptions.remove(&account_id);
+        }
+    }
+
+    pub fn fee_exemptions(&self) -> Vec<AccountId> {
+        self.fee_exemptions.to_vec()
+    }
+
+    /// Fee `sender_id` pays for transferring `amount`.
+    pub fn ft_fee_for(&self, amount: U128, sender_id: AccountId) -> U128 {
+        self.internal_fee_for(amount.0, &sender_id).into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{serde_json, testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    use super::*;
+    use crate::test_utils::get_context;
+
+    fn contract_with_fee() -> Contract {
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000_000.into());
+        for account_id in [accounts(2), accounts(3), accounts(4)].iter() {
+            contract.token.internal_register_account(account_id);
+        }
+        contract.set_transfer_fee(Some(TransferFee {
+            basis_points: 20,
+            maximum_fee: 50.into(),
+            collector_id: accounts(3),
+        }));
+        contract
+    }
+
+    #[test]
+    fn test_fee_for() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = contract_with_fee();
+        assert_eq!(contract.ft_fee_for(10_000.into(), accounts(2)).0, 20);
+        assert_eq!(contract.ft_fee_for(1_000_000.into(), accounts(2)).0, 50);
+        assert_eq!(contract.ft_fee_for(10_000.into(), accounts(3)).0, 0);
+
+        contract.add_fee_exemptions(vec![accounts(2)]);
+        assert_eq!(contract.ft_fee_for(10_000.into(), accounts(2)).0, 0);
+        contract.remove_fee_exemptions(vec![accounts(2)]);
+        assert_eq!(contract.ft_fee_for(10_000.into(), accounts(2)).0, 20);
+
+        contract.set_transfer_fee(None);
+        assert_eq!(contract.ft_fee_for(10_000.into(), accounts(2)).0, 0);
+    }
+
+    #[test]
+    fn test_transfer_with_fee() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = contract_with_fee();
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(2), 10_000.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 990_000);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 9_980);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 20);
+        assert_eq!(contract.ft_total_supply().0, 1_000_000);
+    }
+
+    #[test]
+    fn test_transfer_call_refund_with_fee() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = contract_with_fee();
+        testing_env!(context
+            .attached_deposit(1)
+            .prepaid_gas(Gas::ONE_TERA * 100)
+            .build());
+        contract.ft_transfer_call(accounts(4), 10_000.into(), None, String::new());
+        assert_eq!(contract.ft_balance_of(accounts(4)).0, 9_980);
+
+        // The receiver returned everything it got; the fee stays with the collector.
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(
+                serde_json::to_vec(&U128(9_980)).unwrap()
+            )]
+        );
+        let used = contract.ft_resolve_transfer(accounts(1), accounts(4), 9_980.into());
+        assert_eq!(used.0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 999_980);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 20);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_FEE_COLLECTOR_NOT_REGISTERED (801)")]
+    fn test_unregistered_collector() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.set_transfer_fee(Some(TransferFee {
+            basis_points: 20,
+            maximum_fee: 50.into(),
+            collector_id: accounts(3),
+        }));
+    }
+}
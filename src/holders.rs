@@ -0,0 +1,100 @@
+use crate::*;
+
+impl Contract {
+    /// Keeps `holders` in line with the balance of `account_id`. Must be called after every
+    /// balance change; accounts with a zero balance or without registration are not holders.
+    pub(crate) fn internal_sync_holder(&mut self, account_id: &AccountId) {
+        self.internal_interrupt_audit();
+        match self.token.accounts.get(account_id) {
+            Some(balance) if balance > 0 => {
+                self.holders.insert(account_id);
+            }
+            _ => {
+                self.holders.remove(account_id);
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Accounts with a non-zero balance and their balances, with pagination.
+    /// Contracts upgraded from a layout without the index need the `IndexHolders` migration.
+    pub fn ft_holders(&self, from_index: u64, limit: u64) -> Vec<(AccountId, U128)> {
+        self.abort_if_pause();
+        self.holders
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .map(|account_id| {
+                let balance = self.token.accounts.get(&account_id).unwrap_or(0);
+                (account_id, balance.into())
+            })
+            .collect()
+    }
+
+    pub fn ft_holder_count(&self) -> u64 {
+        self.abort_if_pause();
+        self.holders.len()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::get_context;
+
+    #[test]
+    fn test_holders() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.token.internal_register_account(&accounts(2));
+        contract.token.internal_register_account(&accounts(3));
+        assert_eq!(
+            contract.ft_holders(0, 10),
+            vec![(accounts(1), 1_000.into())]
+        );
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(2), 400.into(), None);
+        testing_env!(context.attached_deposit(0).build());
+        contract.mint(&accounts(3), 100.into());
+        assert_eq!(contract.ft_holder_count(), 3);
+        assert_eq!(
+            contract.ft_holders(1, 1),
+            vec![(accounts(2), U128::from(400))]
+        );
+
+        contract.burn(&accounts(2), 400.into());
+        assert_eq!(contract.ft_holder_count(), 2);
+        assert_eq!(
+            contract.ft_holders(0, 10),
+            vec![(accounts(1), 600.into()), (accounts(3), 100.into())]
+        );
+    }
+
+    #[test]
+    fn test_index_holders_migration() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        // Balances written before the index existed.
+        for account_id in [accounts(2), accounts(3)].iter() {
+            contract.token.internal_register_account(account_id);
+            contract.token.internal_deposit(account_id, 50);
+        }
+        contract.token.internal_register_account(&accounts(4));
+        assert_eq!(contract.ft_holder_count(), 1);
+
+        contract.start_migration(MigrationTask::IndexHolders);
+        contract.enqueue_migration_accounts(vec![accounts(2), accounts(3), accounts(4)]);
+        contract.migrate_step(10);
+        contract.finish_migration();
+        assert_eq!(contract.ft_holder_count(), 3);
+        assert!(!contract.holders.contains(&accounts(4)));
+    }
+}
@@ -0,0 +1,622 @@
+//! Randomized state-machine test of the `Contract` API against a model of balances, the
+//! blacklist and the pause. After every step the total supply has to equal the sum of the
+//! balances, and both have to match the model.
+//!
+//! Unit tests don't roll back state on a panic, so the generator never runs an operation whose
+//! failure the model can't rule out before the first write (a transfer that would only fail
+//! on the unregistered receiver).
+
+use near_sdk::test_utils::{accounts, VMContextBuilder};
+use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::*;
+use near_contract_standards::storage_management::StorageManagement;
+
+const SEEDS: u64 = 32;
+const STEPS: usize = 200;
+const INITIAL_SUPPLY: Balance = 1_000;
+
+/// xorshift64*, so failures can be reproduced from the seed without extra dependencies.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Op {
+    Register(AccountId),
+    Unregister {
+        account_id: AccountId,
+        force: bool,
+    },
+    Mint {
+        caller: AccountId,
+        account_id: AccountId,
+        amount: Balance,
+    },
+    Burn {
+        caller: AccountId,
+        account_id: AccountId,
+        amount: Balance,
+    },
+    Transfer {
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: Balance,
+    },
+    TransferCall {
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: Balance,
+    },
+    /// Resolves the oldest pending `ft_transfer_call`; `None` is a failed `ft_on_transfer`.
+    Resolve {
+        unused: Option<Balance>,
+    },
+    Ban {
+        caller: AccountId,
+        account_id: AccountId,
+    },
+    Unban {
+        caller: AccountId,
+        account_id: AccountId,
+    },
+    DestroyBlackFunds {
+        caller: AccountId,
+        account_id: AccountId,
+    },
+    SetBlacklistMode(BlackListMode),
+    Pause {
+        caller: AccountId,
+    },
+    Resume {
+        caller: AccountId,
+    },
+}
+
+/// What the contract should hold. `balances` has an entry for every registered account.
+struct Model {
+    owner_id: AccountId,
+    balances: HashMap<AccountId, Balance>,
+    banned: HashSet<AccountId>,
+    mode: BlackListMode,
+    paused: bool,
+    total_supply: Balance,
+    pending: VecDeque<(AccountId, AccountId, Balance)>,
+}
+
+/// Outcome the model expects: `Err` holds a part of the panic message.
+type Expected = Result<(), &'static str>;
+
+impl Model {
+    fn new(owner_id: AccountId) -> Self {
+        Self {
+            balances: vec![(owner_id.clone(), INITIAL_SUPPLY)]
+                .into_iter()
+                .collect(),
+            owner_id,
+            banned: HashSet::new(),
+            mode: BlackListMode::Sender,
+            paused: false,
+            total_supply: INITIAL_SUPPLY,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn balance(&self, account_id: &AccountId) -> Balance {
+        self.balances.get(account_id).copied().unwrap_or(0)
+    }
+
+    fn check_owner(&self, caller: &AccountId) -> Expected {
+        if *caller != self.owner_id {
+            return Err("ERR_NOT_OWNER");
+        }
+        Ok(())
+    }
+
+    fn check_role(&self, caller: &AccountId) -> Expected {
+        if *caller != self.owner_id {
+            return Err("ERR_MISSING_ROLE");
+        }
+        Ok(())
+    }
+
+    fn check_paused(&self) -> Expected {
+        if self.paused {
+            return Err("ERR_PAUSED");
+        }
+        Ok(())
+    }
+
+    fn check_withdraw(&self, account_id: &AccountId, amount: Balance) -> Expected {
+        match self.balances.get(account_id) {
+            None => Err("is not registered"),
+            Some(balance) if *balance < amount => Err("doesn't have enough balance"),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Checks of `ft_transfer` and `ft_transfer_call` in the order of the contract. `None` if
+    /// the transfer would only fail on the unregistered receiver, after the sender was debited.
+    fn check_transfer(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> Option<Expected> {
+        let checks = || {
+            self.check_paused()?;
+            if self.banned.contains(sender_id)
+                || (self.mode == BlackListMode::SenderAndReceiver
+                    && self.banned.contains(receiver_id))
+            {
+                return Err("ERR_BANNED");
+            }
+            if sender_id == receiver_id {
+                return Err("Sender and receiver should be different");
+            }
+            if amount == 0 {
+                return Err("The amount should be a positive number");
+            }
+            self.check_withdraw(sender_id, amount)
+        };
+        match checks() {
+            Ok(()) if !self.balances.contains_key(receiver_id) => None,
+            expected => Some(expected),
+        }
+    }
+
+    /// The expected outcome of `op`, `None` if it must not be run.
+    fn expect(&self, op: &Op) -> Option<Expected> {
+        let expected = match op {
+            Op::Register(_) => Ok(()),
+            Op::Unregister { account_id, force } => (|| {
+                self.check_paused()?;
+                if self.banned.contains(account_id) {
+                    return Err("ERR_BANNED");
+                }
+                if self.balance(account_id) > 0 && !force {
+                    return Err("positive balance");
+                }
+                Ok(())
+            })(),
+            Op::Mint {
+                caller, account_id, ..
+            } => (|| {
+                self.check_role(caller)?;
+                self.check_paused()?;
+                if !self.balances.contains_key(account_id) {
+                    return Err("is not registered");
+                }
+                Ok(())
+            })(),
+            Op::Burn {
+                caller,
+                account_id,
+                amount,
+            } => (|| {
+                self.check_role(caller)?;
+                self.check_paused()?;
+                self.check_withdraw(account_id, *amount)
+            })(),
+            Op::Transfer {
+                sender_id,
+                receiver_id,
+                amount,
+            }
+            | Op::TransferCall {
+                sender_id,
+                receiver_id,
+                amount,
+            } => return self.check_transfer(sender_id, receiver_id, *amount),
+            Op::Resolve { .. } if self.pending.is_empty() => return None,
+            Op::Resolve { .. } => Ok(()),
+            Op::Ban { caller, .. } | Op::Unban { caller, .. } => (|| {
+                self.check_role(caller)?;
+                self.check_paused()
+            })(),
+            Op::DestroyBlackFunds { caller, account_id } => (|| {
+                self.check_owner(caller)?;
+                self.check_paused()?;
+                if !self.banned.contains(account_id) {
+                    return Err("ERR_NOT_BANNED");
+                }
+                self.check_withdraw(account_id, self.balance(account_id))
+            })(),
+            Op::SetBlacklistMode(_) => Ok(()),
+            Op::Pause { .. } if self.paused => Err("ERR_NOT_WORKING"),
+            Op::Resume { .. } if !self.paused => Err("ERR_NOT_PAUSED"),
+            Op::Pause { caller } | Op::Resume { caller } => (|| {
+                if *caller != self.owner_id {
+                    return Err("ERR_NOT_OWNER_OR_GUARDIAN");
+                }
+                Ok(())
+            })(),
+        };
+        Some(expected)
+    }
+
+    /// Applies `op`, which succeeded.
+    fn apply(&mut self, op: &Op) {
+        match op {
+            Op::Register(account_id) => {
+                self.balances.entry(account_id.clone()).or_insert(0);
+            }
+            Op::Unregister { account_id, .. } => {
+                if let Some(balance) = self.balances.remove(account_id) {
+                    self.total_supply -= balance;
+                }
+            }
+            Op::Mint {
+                account_id, amount, ..
+            } => {
+                *self.balances.get_mut(account_id).unwrap() += amount;
+                self.total_supply += amount;
+            }
+            Op::Burn {
+                account_id, amount, ..
+            } => {
+                *self.balances.get_mut(account_id).unwrap() -= amount;
+                self.total_supply -= amount;
+            }
+            Op::Transfer {
+                sender_id,
+                receiver_id,
+                amount,
+            }
+            | Op::TransferCall {
+                sender_id,
+                receiver_id,
+                amount,
+            } => {
+                *self.balances.get_mut(sender_id).unwrap() -= amount;
+                *self.balances.get_mut(receiver_id).unwrap() += amount;
+                if matches!(op, Op::TransferCall { .. }) {
+                    self.pending
+                        .push_back((sender_id.clone(), receiver_id.clone(), *amount));
+                }
+            }
+            Op::Resolve { unused } => {
+                let (sender_id, receiver_id, amount) = self.pending.pop_front().unwrap();
+                let unused = unused.map_or(amount, |unused| unused.min(amount));
+                let refund = unused.min(self.balance(&receiver_id));
+                if refund > 0 {
+                    *self.balances.get_mut(&receiver_id).unwrap() -= refund;
+                    match self.balances.get_mut(&sender_id) {
+                        Some(balance) => *balance += refund,
+                        None => self.total_supply -= refund,
+                    }
+                }
+            }
+            Op::Ban { account_id, .. } => {
+                self.banned.insert(account_id.clone());
+            }
+            Op::Unban { account_id, .. } => {
+                self.banned.remove(account_id);
+            }
+            Op::DestroyBlackFunds { account_id, .. } => {
+                let balance = self.balances.get_mut(account_id).unwrap();
+                self.total_supply -= *balance;
+                *balance = 0;
+            }
+            Op::SetBlacklistMode(mode) => self.mode = *mode,
+            Op::Pause { .. } => self.paused = true,
+            Op::Resume { .. } => self.paused = false,
+        }
+    }
+}
+
+struct Harness {
+    context: VMContextBuilder,
+    contract: Contract,
+    model: Model,
+    universe: Vec<AccountId>,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(1))
+            .prepaid_gas(Gas::ONE_TERA * 300);
+        testing_env!(context.build());
+        let contract = Contract::new_default_meta(accounts(1), INITIAL_SUPPLY.into());
+        Self {
+            context,
+            contract,
+            model: Model::new(accounts(1)),
+            universe: (1..6).map(accounts).collect(),
+        }
+    }
+
+    fn amount(&self, rng: &mut Rng, account_id: &AccountId) -> Balance {
+        // Mostly what the account can afford, sometimes more.
+        let balance = self.model.balance(account_id);
+        rng.below((balance + balance / 4 + 2) as u64) as Balance
+    }
+
+    fn caller(&self, rng: &mut Rng) -> AccountId {
+        if rng.chance(85) {
+            self.model.owner_id.clone()
+        } else {
+            rng.pick(&self.universe).clone()
+        }
+    }
+
+    fn generate(&self, rng: &mut Rng) -> Op {
+        let account_id = rng.pick(&self.universe).clone();
+        let other_id = rng.pick(&self.universe).clone();
+        match rng.below(20) {
+            0 | 1 => Op::Register(account_id),
+            2 => Op::Unregister {
+                force: rng.chance(50),
+                account_id,
+            },
+            3 | 4 => Op::Mint {
+                caller: self.caller(rng),
+                amount: rng.below(200) as Balance,
+                account_id,
+            },
+            5 => Op::Burn {
+                caller: self.caller(rng),
+                amount: self.amount(rng, &account_id),
+                account_id,
+            },
+            6..=9 => Op::Transfer {
+                amount: self.amount(rng, &account_id),
+                sender_id: account_id,
+                receiver_id: other_id,
+            },
+            10 | 11 => Op::TransferCall {
+                amount: self.amount(rng, &account_id),
+                sender_id: account_id,
+                receiver_id: other_id,
+            },
+            12 | 13 => Op::Resolve {
+                unused: match rng.below(3) {
+                    0 => None,
+                    _ => Some(rng.below(150) as Balance),
+                },
+            },
+            14 => Op::Ban {
+                caller: self.caller(rng),
+                account_id,
+            },
+            15 => Op::Unban {
+                caller: self.caller(rng),
+                account_id,
+            },
+            16 => Op::DestroyBlackFunds {
+                caller: self.caller(rng),
+                account_id,
+            },
+            17 => Op::SetBlacklistMode(if rng.chance(50) {
+                BlackListMode::Sender
+            } else {
+                BlackListMode::SenderAndReceiver
+            }),
+            18 => Op::Pause {
+                caller: self.caller(rng),
+            },
+            _ => Op::Resume {
+                caller: self.caller(rng),
+            },
+        }
+    }
+
+    fn set_caller(&mut self, caller: &AccountId, deposit: Balance) {
+        testing_env!(self
+            .context
+            .predecessor_account_id(caller.clone())
+            .attached_deposit(deposit)
+            .build());
+    }
+
+    fn run(&mut self, op: &Op) {
+        let owner_id = self.model.owner_id.clone();
+        match op {
+            Op::Register(account_id) => {
+                let deposit = self.contract.storage_balance_bounds().min.0;
+                self.set_caller(account_id, deposit);
+                self.contract
+                    .storage_deposit(Some(account_id.clone()), None);
+            }
+            Op::Unregister { account_id, force } => {
+                self.set_caller(account_id, 1);
+                self.contract.storage_unregister(Some(*force));
+            }
+            Op::Mint {
+                caller,
+                account_id,
+                amount,
+            } => {
+                self.set_caller(caller, 0);
+                self.contract.mint(account_id, (*amount).into());
+            }
+            Op::Burn {
+                caller,
+                account_id,
+                amount,
+            } => {
+                self.set_caller(caller, 0);
+                self.contract.burn(account_id, (*amount).into());
+            }
+            Op::Transfer {
+                sender_id,
+                receiver_id,
+                amount,
+            } => {
+                self.set_caller(sender_id, 1);
+                self.contract
+                    .ft_transfer(receiver_id.clone(), (*amount).into(), None);
+            }
+            Op::TransferCall {
+                sender_id,
+                receiver_id,
+                amount,
+            } => {
+                self.set_caller(sender_id, 1);
+                self.contract.ft_transfer_call(
+                    receiver_id.clone(),
+                    (*amount).into(),
+                    None,
+                    String::new(),
+                );
+            }
+            Op::Resolve { unused } => {
+                let (sender_id, receiver_id, amount) = self.model.pending[0].clone();
+                let result = match unused {
+                    Some(unused) => PromiseResult::Successful(
+                        near_sdk::serde_json::to_vec(&U128(*unused)).unwrap(),
+                    ),
+                    None => PromiseResult::Failed,
+                };
+                testing_env!(
+                    self.context
+                        .predecessor_account_id(accounts(0))
+                        .attached_deposit(0)
+                        .build(),
+                    VMConfig::test(),
+                    RuntimeFeesConfig::test(),
+                    Default::default(),
+                    vec![result]
+                );
+                self.contract
+                    .ft_resolve_transfer(sender_id, receiver_id, amount.into());
+            }
+            Op::Ban { caller, account_id } => {
+                self.set_caller(caller, 0);
+                self.contract.add_to_blacklist(account_id);
+            }
+            Op::Unban { caller, account_id } => {
+                self.set_caller(caller, 0);
+                self.contract.remove_from_blacklist(account_id);
+            }
+            Op::DestroyBlackFunds { caller, account_id } => {
+                self.set_caller(caller, 0);
+                self.contract.destroy_black_funds(account_id);
+            }
+            Op::SetBlacklistMode(mode) => {
+                self.set_caller(&owner_id, 0);
+                self.contract.set_blacklist_mode(*mode);
+            }
+            Op::Pause { caller } => {
+                self.set_caller(caller, 0);
+                self.contract.pause();
+            }
+            Op::Resume { caller } => {
+                self.set_caller(caller, 0);
+                self.contract.resume();
+            }
+        }
+    }
+
+    fn check_invariants(&self, step: &str) {
+        let mut sum = 0;
+        let mut holders = 0;
+        for account_id in &self.universe {
+            let balance = self.contract.token.accounts.get(account_id);
+            assert_eq!(
+                balance,
+                self.model.balances.get(account_id).copied(),
+                "{}: balance of {}",
+                step,
+                account_id
+            );
+            let balance = balance.unwrap_or(0);
+            sum += balance;
+            holders += u64::from(balance > 0);
+            let banned = self.contract.black_list.get(account_id) == Some(BlackListStatus::Banned);
+            assert_eq!(
+                banned,
+                self.model.banned.contains(account_id),
+                "{}: blacklist status of {}",
+                step,
+                account_id
+            );
+        }
+        assert_eq!(
+            self.contract.token.total_supply, sum,
+            "{}: sum of balances",
+            step
+        );
+        assert_eq!(
+            self.contract.token.total_supply, self.model.total_supply,
+            "{}: total supply",
+            step
+        );
+        assert_eq!(self.contract.holders.len(), holders, "{}: holders", step);
+        assert_eq!(
+            self.contract.status == ContractStatus::Paused,
+            self.model.paused,
+            "{}: status",
+            step
+        );
+    }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast::<&str>()
+            .map(|message| message.to_string())
+            .unwrap_or_default(),
+    }
+}
+
+fn run_seed(seed: u64) {
+    let mut rng = Rng(seed);
+    let mut harness = Harness::new();
+    harness.check_invariants(&format!("seed {} init", seed));
+    for i in 0..STEPS {
+        let op = harness.generate(&mut rng);
+        let expected = match harness.model.expect(&op) {
+            Some(expected) => expected,
+            None => continue,
+        };
+        let step = format!("seed {} step {} {:?}", seed, i, op);
+        let result = catch_unwind(AssertUnwindSafe(|| harness.run(&op)));
+        match (result, expected) {
+            (Ok(()), Ok(())) => harness.model.apply(&op),
+            (Err(payload), Err(error)) => {
+                let message = panic_message(payload);
+                assert!(message.contains(error), "{}: {}", step, message);
+            }
+            (Ok(()), Err(error)) => panic!("{}: succeeded, expected {}", step, error),
+            (Err(payload), Ok(())) => panic!("{}: {}", step, panic_message(payload)),
+        }
+        harness.check_invariants(&step);
+    }
+}
+
+#[test]
+fn test_random_operations_keep_invariants() {
+    // The mocked blockchain is thread local, so every seed starts from empty storage.
+    for seed in 1..=SEEDS {
+        if let Err(payload) = std::thread::spawn(move || run_seed(seed)).join() {
+            std::panic::resume_unwind(payload);
+        }
+    }
+}
@@ -1,4 +1,26 @@
-mod event;
+mod abi;
+mod audit;
+mod bridge;
+mod code;
+mod config;
+mod error;
+pub mod event;
+mod fee;
+mod holders;
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod invariants;
+mod limits;
+mod memo;
+mod metadata;
+mod migration;
+mod receiver;
+mod recovery;
+mod roles;
+mod snapshot;
+mod storage;
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod test_utils;
+mod views;
 
 use near_contract_standards::fungible_token::core::FungibleTokenCore;
 use near_contract_standards::fungible_token::metadata::{
@@ -6,16 +28,42 @@ use near_contract_standards::fungible_token::metadata::{
 };
 use near_contract_standards::fungible_token::resolver::FungibleTokenResolver;
 use near_contract_standards::fungible_token::FungibleToken;
+use near_contract_standards::storage_management::StorageManagement;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LazyOption, LookupMap, UnorderedSet};
+use near_sdk::collections::{LazyOption, LookupMap, UnorderedMap, UnorderedSet, Vector};
 use near_sdk::json_types::U128;
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
-    env, log, near_bindgen, sys, AccountId, Balance, Gas, PanicOnDefault, PromiseOrValue,
+    env, log, near_bindgen, sys, AccountId, Balance, Gas, PanicOnDefault, Promise, PromiseOrValue,
+    StorageUsage,
 };
 
 use std::convert::TryFrom;
 
+pub use abi::StandardInfo;
+use audit::Audit;
+pub use audit::AuditProgressView;
+use bridge::Bridge;
+pub use bridge::BridgeChain;
+pub use code::CodeRecord;
+use code::CodeRegistry;
+pub use config::{Distribution, InitConfig, MetadataConfig, MetadataPreset, RoleGrant};
+pub use error::{ContractError, ErrorCodeView};
+pub use fee::TransferFee;
+use limits::TransferLimits;
+pub use limits::{AccountLimit, AllowanceView, GlobalLimit};
+pub use memo::{MemoFormat, MemoPolicy};
+use migration::MigrationProgress;
+pub use migration::{MigrationProgressView, MigrationTask, StateVersion};
+pub use receiver::IncomingPolicy;
+pub use recovery::RecoveryRecord;
+pub use roles::Role;
+pub use snapshot::SnapshotInfo;
+use snapshot::Snapshots;
+use storage::SponsoredStorage;
+pub use storage::Sponsorship;
+pub use views::AccountInfo;
+
 #[derive(
     BorshDeserialize, BorshSerialize, Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize,
 )]
@@ -27,6 +75,26 @@ pub enum BlackListStatus {
     Banned,
 }
 
+/// Which side of a transfer the blacklist is checked for.
+#[derive(
+    BorshDeserialize,
+    BorshSerialize,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    Debug,
+    Default,
+    Serialize,
+    Deserialize,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub enum BlackListMode {
+    #[default]
+    Sender,
+    SenderAndReceiver,
+}
+
 #[derive(
     BorshDeserialize, BorshSerialize, Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize,
 )]
@@ -34,6 +102,7 @@ pub enum BlackListStatus {
 pub enum ContractStatus {
     Working,
     Paused,
+    Migrating,
 }
 
 impl std::fmt::Display for ContractStatus {
@@ -41,6 +110,7 @@ impl std::fmt::Display for ContractStatus {
         match self {
             ContractStatus::Working => write!(f, "working"),
             ContractStatus::Paused => write!(f, "paused"),
+            ContractStatus::Migrating => write!(f, "migrating"),
         }
     }
 }
@@ -55,6 +125,23 @@ pub struct Contract {
     guardians: UnorderedSet<AccountId>,
     black_list: LookupMap<AccountId, BlackListStatus>,
     status: ContractStatus,
+    migration: Option<MigrationProgress>,
+    metadata_version: u64,
+    roles: UnorderedMap<AccountId, Vec<Role>>,
+    supply_cap: Option<Balance>,
+    blacklist_mode: BlackListMode,
+    snapshots: Snapshots,
+    holders: UnorderedSet<AccountId>,
+    transfer_fee: Option<TransferFee>,
+    fee_exemptions: UnorderedSet<AccountId>,
+    limits: TransferLimits,
+    memo_policies: LookupMap<AccountId, MemoPolicy>,
+    incoming_policies: LookupMap<AccountId, IncomingPolicy>,
+    recoveries: Vector<RecoveryRecord>,
+    sponsorship: SponsoredStorage,
+    force_close_allowed: bool,
+    audit: Option<Audit>,
+    bridge: Bridge,
 }
 
 const DATA_IMAGE_SVG_NEAR_ICON: &str =
@@ -66,49 +153,37 @@ impl Contract {
     // default metadata (for example purposes only).
     #[init]
     pub fn new_default_meta(owner_id: AccountId, total_supply: U128) -> Self {
-        Self::new(
-            owner_id,
-            total_supply,
-            FungibleTokenMetadata {
-                spec: FT_METADATA_SPEC.to_string(),
-                name: "Tether USD".to_string(),
-                symbol: "USDt".to_string(),
-                icon: Some(DATA_IMAGE_SVG_NEAR_ICON.to_string()),
-                reference: None,
-                reference_hash: None,
-                decimals: 6,
-            },
-        )
+        Self::new(owner_id, total_supply, MetadataPreset::Usdt.metadata())
     }
 
     // Initializes the contract with the given total supply owned by the given `owner_id` with
     // the given fungible token metadata.
     #[init]
     pub fn new(owner_id: AccountId, total_supply: U128, metadata: FungibleTokenMetadata) -> Self {
-        metadata.assert_valid();
-        let mut this = Self {
-            owner_id: owner_id.clone(),
-            proposed_owner_id: owner_id.clone(),
-            token: FungibleToken::new(b"a".to_vec()),
-            guardians: UnorderedSet::new(b"c".to_vec()),
-            metadata: LazyOption::new(b"m".to_vec(), Some(&metadata)),
-            black_list: LookupMap::new(b"b".to_vec()),
-            status: ContractStatus::Working,
-        };
-        this.token.internal_register_account(&owner_id);
-        this.token.internal_deposit(&owner_id, total_supply.into());
-        event::emit::ft_mint(&owner_id, total_supply.into(), Some("Initial supply"));
-        this
+        let mut distribution = vec![];
+        if total_supply.0 > 0 {
+            distribution.push(Distribution {
+                account_id: owner_id.clone(),
+                amount: total_supply,
+            });
+        }
+        Self::new_with_config(InitConfig {
+            owner_id,
+            metadata: MetadataConfig::Custom(metadata),
+            guardians: vec![],
+            roles: vec![],
+            supply_cap: None,
+            distribution,
+            blacklist_mode: BlackListMode::Sender,
+        })
     }
 
     pub fn upgrade_name_symbol(&mut self, name: String, symbol: String) {
         self.abort_if_not_owner();
-        let metadata = self.metadata.get();
-        if let Some(mut metadata) = metadata {
-            metadata.name = name;
-            metadata.symbol = symbol;
-            self.metadata.replace(&metadata);
-        }
+        let mut metadata = self.internal_metadata();
+        metadata.name = name;
+        metadata.symbol = symbol;
+        self.internal_set_metadata(metadata);
     }
 
     pub fn propose_new_owner(&mut self, proposed_owner_id: AccountId) {
@@ -117,8 +192,12 @@ impl Contract {
     }
 
     pub fn accept_ownership(&mut self) {
-        assert_ne!(self.owner_id, self.proposed_owner_id);
-        assert_eq!(env::predecessor_account_id(), self.proposed_owner_id);
+        if self.owner_id == self.proposed_owner_id {
+            ContractError::OwnershipNotProposed.panic();
+        }
+        if env::predecessor_account_id() != self.proposed_owner_id {
+            ContractError::NotProposedOwner.panic();
+        }
         self.owner_id = self.proposed_owner_id.clone();
     }
 
@@ -127,7 +206,7 @@ impl Contract {
         self.abort_if_not_owner();
         for guardian in guardians {
             if !self.guardians.insert(&guardian) {
-                env::panic_str(&format!("The guardian '{}' already exists", guardian));
+                ContractError::GuardianExists.panic_with(guardian.as_str());
             }
         }
     }
@@ -137,7 +216,7 @@ impl Contract {
         self.abort_if_not_owner();
         for guardian in guardians {
             if !self.guardians.remove(&guardian) {
-                env::panic_str(&format!("The guardian '{}' doesn't exist", guardian));
+                ContractError::GuardianNotFound.panic_with(guardian.as_str());
             }
         }
     }
@@ -148,11 +227,9 @@ impl Contract {
 
     pub fn upgrade_icon(&mut self, data: String) {
         self.abort_if_not_owner();
-        let metadata = self.metadata.get();
-        if let Some(mut metadata) = metadata {
-            metadata.icon = Some(data);
-            self.metadata.replace(&metadata);
-        }
+        let mut metadata = self.internal_metadata();
+        metadata.icon = Some(data);
+        self.internal_set_metadata(metadata);
     }
 
     pub fn get_blacklist_status(&self, account_id: &AccountId) -> BlackListStatus {
@@ -164,13 +241,13 @@ impl Contract {
     }
 
     pub fn add_to_blacklist(&mut self, account_id: &AccountId) {
-        self.abort_if_not_owner();
+        self.abort_if_not_owner_or_role(Role::Blacklister);
         self.abort_if_pause();
         self.black_list.insert(account_id, &BlackListStatus::Banned);
     }
 
     pub fn remove_from_blacklist(&mut self, account_id: &AccountId) {
-        self.abort_if_not_owner();
+        self.abort_if_not_owner_or_role(Role::Blacklister);
         self.abort_if_pause();
         self.black_list
             .insert(account_id, &BlackListStatus::Allowable);
@@ -180,10 +257,9 @@ impl Contract {
         self.abort_if_not_owner();
         self.abort_if_pause();
 
-        assert_eq!(
-            self.get_blacklist_status(&account_id),
-            BlackListStatus::Banned
-        );
+        if self.get_blacklist_status(&account_id) != BlackListStatus::Banned {
+            ContractError::NotBanned.panic_with(account_id.as_str());
+        }
 
         let black_balance = self.ft_balance_of(account_id.clone());
 
@@ -200,11 +276,10 @@ impl Contract {
     // Creates `amount` tokens and assigns them to `account`, increasing
     // the total supply.
     pub fn mint(&mut self, account_id: &AccountId, amount: U128) {
-        self.abort_if_not_owner();
+        self.abort_if_not_owner_or_role(Role::Minter);
         self.abort_if_pause();
 
-        self.token.internal_deposit(account_id, amount.into());
-        event::emit::ft_mint(account_id, amount.into(), None);
+        self.internal_mint(account_id, amount.into(), None);
     }
 
     // Redeem tokens (burn).
@@ -221,23 +296,30 @@ impl Contract {
     // if the balance must be enough to cover the redeem
     // or the call will fail.
     pub fn burn(&mut self, account_id: &AccountId, amount: U128) {
-        self.abort_if_not_owner();
+        self.abort_if_not_owner_or_role(Role::Minter);
         self.abort_if_pause();
 
+        self.internal_checkpoint_balance(account_id);
+        self.internal_checkpoint_supply();
         self.token.internal_withdraw(account_id, amount.into());
+        self.internal_sync_holder(account_id);
         event::emit::ft_burn(account_id, amount.into(), None);
     }
 
     // If we have to pause contract
     pub fn pause(&mut self) {
-        assert_eq!(self.status, ContractStatus::Working);
+        if self.status != ContractStatus::Working {
+            ContractError::NotWorking.panic();
+        }
         self.abort_if_not_owner_or_guardian();
         self.status = ContractStatus::Paused;
     }
 
     // If we have to resume contract
     pub fn resume(&mut self) {
-        assert_eq!(self.status, ContractStatus::Paused);
+        if self.status != ContractStatus::Paused {
+            ContractError::NotPaused.panic();
+        }
         self.abort_if_not_owner_or_guardian();
         self.status = ContractStatus::Working;
     }
@@ -251,8 +333,7 @@ impl Contract {
      */
     pub fn name(&mut self) -> String {
         self.abort_if_pause();
-        let metadata = self.metadata.get();
-        metadata.expect("Unable to get name").name
+        self.internal_metadata().name
     }
 
     /**
@@ -260,8 +341,7 @@ impl Contract {
      */
     pub fn symbol(&mut self) -> String {
         self.abort_if_pause();
-        let metadata = self.metadata.get();
-        metadata.expect("Unable to get symbol").symbol
+        self.internal_metadata().symbol
     }
 
     /**
@@ -269,8 +349,7 @@ impl Contract {
      */
     pub fn decimals(&mut self) -> u8 {
         self.abort_if_pause();
-        let metadata = self.metadata.get();
-        metadata.expect("Unable to get decimals").decimals
+        self.internal_metadata().decimals
     }
 
     pub fn version(&self) -> String {
@@ -281,26 +360,17 @@ impl Contract {
         self.owner_id.clone()
     }
 
-    /// Should only be called by this contract on migration.
-    /// This is NOOP implementation. KEEP IT if you haven't changed contract state.
-    /// This method is called from `upgrade()` method.
-    /// For next version upgrades, change this function.
-    #[init(ignore_state)]
-    #[private]
-    pub fn migrate() -> Self {
-        let this: Contract = env::state_read().expect("Contract is not initialized.");
-        this
-    }
-
     fn abort_if_pause(&self) {
-        if self.status == ContractStatus::Paused {
-            env::panic_str("Operation aborted because the contract under maintenance")
+        match self.status {
+            ContractStatus::Working => {}
+            ContractStatus::Paused => ContractError::Paused.panic(),
+            ContractStatus::Migrating => ContractError::Migrating.panic(),
         }
     }
 
     fn abort_if_not_owner(&self) {
         if env::predecessor_account_id() != self.owner_id {
-            env::panic_str("This method might be called only by owner account")
+            ContractError::NotOwner.panic()
         }
     }
 
@@ -308,18 +378,61 @@ impl Contract {
         if env::predecessor_account_id() != self.owner_id
             && !self.guardians.contains(&env::predecessor_account_id())
         {
-            env::panic_str("This method can be called only by owner or guardian")
+            ContractError::NotOwnerOrGuardian.panic()
         }
     }
 
     fn abort_if_blacklisted(&self, account_id: &AccountId) {
         if self.get_blacklist_status(account_id) != BlackListStatus::Allowable {
-            env::panic_str(&format!("Account '{}' is banned", account_id));
+            ContractError::Banned.panic_with(account_id.as_str());
         }
     }
 
+    fn abort_if_transfer_blacklisted(&self, receiver_id: &AccountId) {
+        self.abort_if_blacklisted(&env::predecessor_account_id());
+        if self.blacklist_mode == BlackListMode::SenderAndReceiver {
+            self.abort_if_blacklisted(receiver_id);
+        }
+    }
+
+    /// Charges the predecessor for the storage added since `initial_storage_usage` and refunds
+    /// the rest of the attached deposit, or refunds the storage that was released.
+    fn internal_settle_storage(&self, initial_storage_usage: StorageUsage) {
+        let storage_usage = env::storage_usage();
+        let attached = env::attached_deposit();
+        let refund = if storage_usage >= initial_storage_usage {
+            let required =
+                Balance::from(storage_usage - initial_storage_usage) * env::storage_byte_cost();
+            if attached < required {
+                ContractError::InsufficientDeposit.panic_with(&required.to_string());
+            }
+            attached - required
+        } else {
+            let released =
+                Balance::from(initial_storage_usage - storage_usage) * env::storage_byte_cost();
+            attached + released
+        };
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+    }
+
+    fn internal_metadata(&self) -> FungibleTokenMetadata {
+        self.metadata
+            .get()
+            .unwrap_or_else(|| ContractError::MetadataNotSet.panic())
+    }
+
     fn on_account_closed(&mut self, account_id: AccountId, balance: Balance) {
+        // The account is already removed and its balance burned.
+        self.internal_checkpoint_balance_with(&account_id, balance);
+        let total_supply = self.token.total_supply + balance;
+        self.internal_checkpoint_supply_with(total_supply);
+        self.internal_sync_holder(&account_id);
         log!("Closed @{} with {}", account_id, balance);
+        if balance > 0 {
+            event::emit::ft_burn(&account_id, balance, Some("account closed"));
+        }
     }
 
     fn on_tokens_burned(&mut self, account_id: &AccountId, amount: Balance) {
@@ -332,21 +445,32 @@ pub fn upgrade() {
     env::setup_panic_hook();
 
     let contract: Contract = env::state_read().expect("Contract is not initialized");
-    contract.abort_if_not_owner();
+    contract.abort_if_not_owner_or_role(Role::Upgrader);
 
     const MIGRATE_METHOD_NAME: &[u8; 7] = b"migrate";
     const UPGRADE_GAS_LEFTOVER: Gas = Gas(5_000_000_000_000);
 
+    // Keep the blob so `rollback()` can return to it after the next upgrade. The caller pays
+    // its storage with the attached deposit.
+    let code = env::input().unwrap_or_else(|| ContractError::EmptyCode.panic());
+    let initial_storage_usage = env::storage_usage();
+    let mut registry = CodeRegistry::load();
+    registry.stage_deployment(&code);
+    registry.save();
+    contract.internal_settle_storage(initial_storage_usage);
+
     unsafe {
-        // Load code into register 0 result from the input argument if factory call or from promise if callback.
-        sys::input(0);
-        // Create a promise batch to upgrade current contract with code from register 0.
+        // Create a promise batch to upgrade current contract with the code from the input.
         let promise_id = sys::promise_batch_create(
             env::current_account_id().as_bytes().len() as u64,
             env::current_account_id().as_bytes().as_ptr() as u64,
         );
-        // Deploy the contract code from register 0.
-        sys::promise_batch_action_deploy_contract(promise_id, u64::MAX, 0);
+        // Deploy the contract code.
+        sys::promise_batch_action_deploy_contract(
+            promise_id,
+            code.len() as u64,
+            code.as_ptr() as u64,
+        );
         // Call promise to migrate the state.
         // Batched together to fail upgrade if migration fails.
         sys::promise_batch_action_function_call(
@@ -356,7 +480,7 @@ pub fn upgrade() {
             0,
             0,
             0,
-            (env::prepaid_gas() - env::used_gas() - UPGRADE_GAS_LEFTOVER).0,
+            code::remaining_gas(UPGRADE_GAS_LEFTOVER).0,
         );
         sys::promise_return(promise_id);
     }
@@ -370,8 +494,18 @@ impl FungibleTokenCore for Contract {
     #[payable]
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
         self.abort_if_pause();
-        self.abort_if_blacklisted(&env::predecessor_account_id());
-        self.token.ft_transfer(receiver_id, amount, memo);
+        self.abort_if_transfer_blacklisted(&receiver_id);
+        self.abort_if_incoming_rejected(&receiver_id, false);
+        self.abort_if_invalid_memo(&receiver_id, memo.as_deref());
+        let sender_id = env::predecessor_account_id();
+        self.internal_sponsor_registration(&sender_id, &receiver_id);
+        self.internal_apply_transfer_limits(&sender_id, amount.0);
+        let amount = self.internal_charge_fee(&sender_id, amount);
+        self.internal_checkpoint_balance(&sender_id);
+        self.internal_checkpoint_balance(&receiver_id);
+        self.token.ft_transfer(receiver_id.clone(), amount, memo);
+        self.internal_sync_holder(&sender_id);
+        self.internal_sync_holder(&receiver_id);
     }
 
     #[payable]
@@ -383,9 +517,23 @@ impl FungibleTokenCore for Contract {
         msg: String,
     ) -> PromiseOrValue<U128> {
         self.abort_if_pause();
-        self.abort_if_blacklisted(&env::predecessor_account_id());
-        self.token
-            .ft_transfer_call(receiver_id.clone(), amount, memo, msg)
+        self.abort_if_transfer_blacklisted(&receiver_id);
+        self.abort_if_incoming_rejected(&receiver_id, true);
+        self.abort_if_invalid_memo(&receiver_id, memo.as_deref());
+        let sender_id = env::predecessor_account_id();
+        self.internal_sponsor_registration(&sender_id, &receiver_id);
+        self.internal_apply_transfer_limits(&sender_id, amount.0);
+        // The receiver is called with the amount after the fee, so that is also the most
+        // `ft_resolve_transfer` can refund. The fee itself is not refunded.
+        let amount = self.internal_charge_fee(&sender_id, amount);
+        self.internal_checkpoint_balance(&sender_id);
+        self.internal_checkpoint_balance(&receiver_id);
+        let result = self
+            .token
+            .ft_transfer_call(receiver_id.clone(), amount, memo, msg);
+        self.internal_sync_holder(&sender_id);
+        self.internal_sync_holder(&receiver_id);
+        result
     }
 
     fn ft_total_supply(&self) -> U128 {
@@ -409,9 +557,14 @@ impl FungibleTokenResolver for Contract {
         amount: U128,
     ) -> U128 {
         let sender_id: AccountId = sender_id.into();
+        self.internal_checkpoint_balance(&sender_id);
+        self.internal_checkpoint_balance(&receiver_id);
+        self.internal_checkpoint_supply();
         let (used_amount, burned_amount) =
             self.token
-                .internal_ft_resolve_transfer(&sender_id, receiver_id, amount);
+                .internal_ft_resolve_transfer(&sender_id, receiver_id.clone(), amount);
+        self.internal_sync_holder(&sender_id);
+        self.internal_sync_holder(&receiver_id);
         if burned_amount > 0 {
             self.on_tokens_burned(
                 &AccountId::try_from(sender_id).expect("Couldn't validate sender address"),
@@ -422,33 +575,23 @@ impl FungibleTokenResolver for Contract {
     }
 }
 
-near_contract_standards::impl_fungible_token_storage!(Contract, token, on_account_closed);
-
 #[near_bindgen]
 impl FungibleTokenMetadataProvider for Contract {
     fn ft_metadata(&self) -> FungibleTokenMetadata {
-        self.metadata.get().unwrap()
+        self.internal_metadata()
     }
 }
 
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::test_utils::accounts;
     use near_sdk::{testing_env, Balance};
 
     use super::*;
+    use crate::test_utils::get_context;
 
     const TOTAL_SUPPLY: Balance = 1_000_000_000_000_000;
 
-    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
-        let mut builder = VMContextBuilder::new();
-        builder
-            .current_account_id(accounts(0))
-            .signer_account_id(predecessor_account_id.clone())
-            .predecessor_account_id(predecessor_account_id);
-        builder
-    }
-
     #[test]
     fn test_new() {
         let mut context = get_context(accounts(1));
@@ -488,7 +631,7 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
+    #[should_panic(expected = "ERR_NOT_OWNER (100)")]
     fn test_extend_guardians_by_user() {
         let mut context = get_context(accounts(1));
         testing_env!(context.build());
@@ -690,7 +833,7 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
+    #[should_panic(expected = "ERR_NOT_WORKING (202)")]
     fn test_contract_status_pause() {
         let context = get_context(accounts(1));
         testing_env!(context.build());
@@ -701,7 +844,7 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
+    #[should_panic(expected = "ERR_NOT_PAUSED (203)")]
     fn test_contract_status_resume() {
         let context = get_context(accounts(1));
         testing_env!(context.build());
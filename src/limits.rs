@@ -0,0 +1,350 @@
+use crate::*;
+use near_sdk::json_types::U64;
+
+const HOUR: u64 = 3_600_000_000_000;
+/// Length of the per-account rolling window, in hours.
+const WINDOW_HOURS: u64 = 24;
+
+/// Velocity limits of a flagged sender. The window limit applies to the last 24 hours, tracked
+/// in hourly buckets.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountLimit {
+    pub max_per_transfer: Option<U128>,
+    pub max_per_window: Option<U128>,
+}
+
+/// Circuit breaker on the volume of all transfers within fixed windows of `window_blocks`
+/// blocks. A transfer that would exceed `max_volume` is rejected, and the one that reaches it
+/// trips the breaker, emitting the event in its own successful receipt; until the window ends,
+/// or the breaker is reset, transfers are rejected.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GlobalLimit {
+    pub window_blocks: U64,
+    pub max_volume: U128,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AllowanceView {
+    pub max_per_transfer: Option<U128>,
+    pub window_remaining: Option<U128>,
+    pub global_remaining: Option<U128>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TransferLimits {
+    accounts: LookupMap<AccountId, AccountLimit>,
+    /// Transferred amounts of the limited accounts, as `(hour, amount)` buckets.
+    usage: LookupMap<AccountId, Vec<(u64, Balance)>>,
+    global: Option<GlobalLimit>,
+    window: u64,
+    volume: Balance,
+    tripped: bool,
+}
+
+impl TransferLimits {
+    pub(crate) fn new() -> Self {
+        Self {
+            accounts: LookupMap::new(b"l".to_vec()),
+            usage: LookupMap::new(b"y".to_vec()),
+            global: None,
+            window: 0,
+            volume: 0,
+            tripped: false,
+        }
+    }
+
+    fn current_hour() -> u64 {
+        env::block_timestamp() / HOUR
+    }
+
+    /// Buckets of `account_id` that are still inside the rolling window.
+    fn recent_usage(&self, account_id: &AccountId) -> Vec<(u64, Balance)> {
+        let since = (Self::current_hour() + 1).saturating_sub(WINDOW_HOURS);
+        let mut usage = self.usage.get(account_id).unwrap_or_default();
+        usage.retain(|(hour, _)| *hour >= since);
+        usage
+    }
+
+    /// Volume of the current global window, 0 if the stored one has ended, and whether the
+    /// breaker is tripped in it.
+    fn current_volume(&self, global: &GlobalLimit) -> (u64, Balance, bool) {
+        let window = env::block_height() / std::cmp::max(global.window_blocks.0, 1);
+        if window == self.window {
+            let tripped = self.tripped || self.volume >= global.max_volume.0;
+            (window, self.volume, tripped)
+        } else {
+            (window, 0, false)
+        }
+    }
+}
+
+impl Contract {
+    /// Checks the limits for `sender_id` sending `amount` and records the transfer.
+    pub(crate) fn internal_apply_transfer_limits(
+        &mut self,
+        sender_id: &AccountId,
+        amount: Balance,
+    ) {
+        if let Some(limit) = self.limits.accounts.get(sender_id) {
+            if let Some(max) = limit.max_per_transfer {
+                if amount > max.0 {
+                    event::emit::transfer_limit_exceeded(sender_id, amount, "per_transfer", max.0);
+                    ContractError::TransferLimitExceeded.panic_with(sender_id.as_str());
+                }
+            }
+            if let Some(max) = limit.max_per_window {
+                let mut usage = self.limits.recent_usage(sender_id);
+                let spent: Balance = usage.iter().map(|(_, amount)| amount).sum();
+                if spent + amount > max.0 {
+                    event::emit::transfer_limit_exceeded(sender_id, amount, "per_window", max.0);
+                    ContractError::WindowLimitExceeded.panic_with(sender_id.as_str());
+                }
+                let hour = TransferLimits::current_hour();
+                match usage.last_mut() {
+                    Some((last, spent)) if *last == hour => *spent += amount,
+                    _ => usage.push((hour, amount)),
+                }
+                self.limits.usage.insert(sender_id, &usage);
+            }
+        }
+
+        if let Some(global) = self.limits.global.clone() {
+            let (window, volume, tripped) = self.limits.current_volume(&global);
+            if tripped {
+                ContractError::CircuitBreakerTripped.panic();
+            }
+            let volume = volume + amount;
+            if volume > global.max_volume.0 {
+                ContractError::GlobalLimitExceeded.panic();
+            }
+            self.limits.window = window;
+            self.limits.volume = volume;
+            self.limits.tripped = volume >= global.max_volume.0;
+            if self.limits.tripped {
+                event::emit::circuit_breaker_tripped(
+                    self.limits.volume,
+                    window * global.window_blocks.0,
+                );
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// `None` removes the limits of `account_id`.
+    pub fn set_transfer_limit(&mut self, account_id: AccountId, limit: Option<AccountLimit>) {
+        self.abort_if_not_owner_or_role(Role::Compliance);
+        match limit {
+            Some(limit) => {
+                self.limits.accounts.insert(&account_id, &limit);
+            }
+            None => {
+                self.limits.accounts.remove(&account_id);
+                self.limits.usage.remove(&account_id);
+            }
+        }
+    }
+
+    pub fn transfer_limit_of(&self, account_id: AccountId) -> Option<AccountLimit> {
+        self.limits.accounts.get(&account_id)
+    }
+
+    /// `None` disables the circuit breaker.
+    pub fn set_global_transfer_limit(&mut self, limit: Option<GlobalLimit>) {
+        self.abort_if_not_owner_or_role(Role::Compliance);
+        self.limits.global = limit;
+        self.limits.volume = 0;
+        self.limits.tripped = false;
+    }
+
+    pub fn global_transfer_limit(&self) -> Option<GlobalLimit> {
+        self.limits.global.clone()
+    }
+
+    /// Lets transfers through again before the tripped window ends, starting the volume of the
+    /// window from zero.
+    pub fn reset_circuit_breaker(&mut self) {
+        self.abort_if_not_owner_or_role(Role::Compliance);
+        self.limits.volume = 0;
+        self.limits.tripped = false;
+    }
+
+    /// What `account_id` can still send under each of the limits that apply to it.
+    pub fn remaining_allowance(&self, account_id: AccountId) -> AllowanceView {
+        let limit = self.limits.accounts.get(&account_id);
+        let window_remaining = limit
+            .as_ref()
+            .and_then(|limit| limit.max_per_window)
+            .map(|max| {
+                let usage = self.limits.recent_usage(&account_id);
+                let spent: Balance = usage.iter().map(|(_, amount)| amount).sum();
+                max.0.saturating_sub(spent).into()
+            });
+        let global_remaining =
+            self.limits
+                .global
+                .as_ref()
+                .map(|global| match self.limits.current_volume(global) {
+                    (_, _, true) => 0.into(),
+                    (_, volume, false) => global.max_volume.0.saturating_sub(volume).into(),
+                });
+        AllowanceView {
+            max_per_transfer: limit.and_then(|limit| limit.max_per_transfer),
+            window_remaining,
+            global_remaining,
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::get_context;
+
+    fn setup(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000_000.into());
+        contract.token.internal_register_account(&accounts(2));
+        contract.grant_role(accounts(3), Role::Compliance);
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.set_transfer_limit(
+            accounts(1),
+            Some(AccountLimit {
+                max_per_transfer: Some(500.into()),
+                max_per_window: Some(1_000.into()),
+            }),
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract
+    }
+
+    #[test]
+    fn test_window_limit() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup(&mut context);
+        contract.ft_transfer(accounts(2), 500.into(), None);
+        contract.ft_transfer(accounts(2), 300.into(), None);
+        let allowance = contract.remaining_allowance(accounts(1));
+        assert_eq!(allowance.max_per_transfer, Some(500.into()));
+        assert_eq!(allowance.window_remaining, Some(200.into()));
+        assert_eq!(allowance.global_remaining, None);
+
+        // A day later the earlier transfers are out of the window.
+        testing_env!(context.block_timestamp(WINDOW_HOURS * HOUR).build());
+        assert_eq!(
+            contract.remaining_allowance(accounts(1)).window_remaining,
+            Some(1_000.into())
+        );
+        contract.ft_transfer(accounts(2), 500.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_WINDOW_LIMIT_EXCEEDED (901)")]
+    fn test_window_limit_exceeded() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup(&mut context);
+        contract.ft_transfer(accounts(2), 500.into(), None);
+        contract.ft_transfer(accounts(2), 500.into(), None);
+        contract.ft_transfer(accounts(2), 1.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_TRANSFER_LIMIT_EXCEEDED (900)")]
+    fn test_transfer_limit_exceeded() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup(&mut context);
+        contract.ft_transfer(accounts(2), 501.into(), None);
+    }
+
+    #[test]
+    fn test_transfer_limit_event() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup(&mut context);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.ft_transfer(accounts(2), 501.into(), None)
+        }));
+        assert!(result.is_err());
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"tether_token","version":"1.0.0","event":"transfer_limit_exceeded","data":[{"account_id":"bob","amount":"501","limit":"per_transfer","max":"500"}]}"#
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_GLOBAL_LIMIT_EXCEEDED (903)")]
+    fn test_global_limit_exceeded() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup(&mut context);
+        contract.set_transfer_limit(accounts(1), None);
+        contract.set_global_transfer_limit(Some(GlobalLimit {
+            window_blocks: 100.into(),
+            max_volume: 1_000.into(),
+        }));
+        contract.ft_transfer(accounts(2), 600.into(), None);
+        contract.ft_transfer(accounts(2), 401.into(), None);
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup(&mut context);
+        contract.set_transfer_limit(accounts(1), None);
+        contract.set_global_transfer_limit(Some(GlobalLimit {
+            window_blocks: 100.into(),
+            max_volume: 1_000.into(),
+        }));
+        contract.ft_transfer(accounts(2), 600.into(), None);
+        contract.ft_transfer(accounts(2), 400.into(), None);
+        assert_eq!(
+            contract.remaining_allowance(accounts(1)).global_remaining,
+            Some(0.into())
+        );
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.ft_transfer(accounts(2), 1.into(), None)
+        }));
+        assert!(result.is_err());
+
+        testing_env!(context.block_index(100).build());
+        contract.ft_transfer(accounts(2), 1.into(), None);
+        assert_eq!(
+            contract.remaining_allowance(accounts(1)).global_remaining,
+            Some(999.into())
+        );
+    }
+
+    #[test]
+    fn test_transfer_after_circuit_breaker_reset() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup(&mut context);
+        contract.set_transfer_limit(accounts(1), None);
+        contract.set_global_transfer_limit(Some(GlobalLimit {
+            window_blocks: 100.into(),
+            max_volume: 1_000.into(),
+        }));
+        contract.ft_transfer(accounts(2), 1_000.into(), None);
+        assert_eq!(
+            get_logs()[0],
+            r#"EVENT_JSON:{"standard":"tether_token","version":"1.0.0","event":"circuit_breaker_tripped","data":[{"volume":"1000","window_start":"0"}]}"#
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.reset_circuit_breaker();
+        assert_eq!(
+            contract.remaining_allowance(accounts(1)).global_remaining,
+            Some(1_000.into())
+        );
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), 600.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(2)), 1_600.into());
+    }
+}
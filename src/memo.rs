@@ -0,0 +1,149 @@
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum MemoFormat {
+    /// Any text of at most `max_length` bytes.
+    Text { max_length: u16 },
+    /// A destination tag or invoice id: 1 to `max_digits` decimal digits.
+    Numeric { max_digits: u8 },
+    /// A JSON object, e.g. a travel-rule payload.
+    JsonObject,
+}
+
+impl MemoFormat {
+    fn matches(&self, memo: &str) -> bool {
+        match self {
+            MemoFormat::Text { max_length } => memo.len() <= usize::from(*max_length),
+            MemoFormat::Numeric { max_digits } => {
+                !memo.is_empty()
+                    && memo.len() <= usize::from(*max_digits)
+                    && memo.bytes().all(|b| b.is_ascii_digit())
+            }
+            MemoFormat::JsonObject => matches!(
+                near_sdk::serde_json::from_str(memo),
+                Ok(near_sdk::serde_json::Value::Object(_))
+            ),
+        }
+    }
+}
+
+/// Memo a receiver expects on incoming `ft_transfer` and `ft_transfer_call`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MemoPolicy {
+    /// Reject transfers without a memo. A memo that is given always has to match `format`.
+    pub required: bool,
+    pub format: MemoFormat,
+}
+
+impl Contract {
+    pub(crate) fn abort_if_invalid_memo(&self, receiver_id: &AccountId, memo: Option<&str>) {
+        let policy = match self.memo_policies.get(receiver_id) {
+            Some(policy) => policy,
+            None => return,
+        };
+        match memo {
+            None if policy.required => ContractError::MemoRequired.panic_with(receiver_id.as_str()),
+            Some(memo) if !policy.format.matches(memo) => {
+                ContractError::InvalidMemo.panic_with(receiver_id.as_str())
+            }
+            _ => {}
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the memo policy for transfers to the caller, `None` removes it. The storage is paid
+    /// with the attached deposit; the excess is refunded.
+    #[payable]
+    pub fn set_memo_policy(&mut self, policy: Option<MemoPolicy>) {
+        let account_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        match policy {
+            Some(policy) => self.memo_policies.insert(&account_id, &policy),
+            None => self.memo_policies.remove(&account_id),
+        };
+        self.internal_settle_storage(initial_storage_usage);
+    }
+
+    pub fn memo_policy_of(&self, account_id: AccountId) -> Option<MemoPolicy> {
+        self.memo_policies.get(&account_id)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    use super::*;
+    use crate::test_utils::get_context;
+
+    fn setup(context: &mut VMContextBuilder, policy: MemoPolicy) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.token.internal_register_account(&accounts(2));
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.set_memo_policy(Some(policy));
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract
+    }
+
+    #[test]
+    fn test_numeric_memo() {
+        let mut context = get_context(accounts(1));
+        let policy = MemoPolicy {
+            required: true,
+            format: MemoFormat::Numeric { max_digits: 10 },
+        };
+        let mut contract = setup(&mut context, policy.clone());
+        assert_eq!(contract.memo_policy_of(accounts(2)), Some(policy));
+        contract.ft_transfer(accounts(2), 10.into(), Some("1234567890".to_string()));
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 10);
+
+        let format = MemoFormat::Numeric { max_digits: 10 };
+        assert!(!format.matches("12345678901"));
+        assert!(!format.matches("12a"));
+        assert!(!format.matches(""));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "ERR_MEMO_REQUIRED (1000): A memo is required by the receiver: charlie"
+    )]
+    fn test_missing_memo() {
+        let mut context = get_context(accounts(1));
+        let policy = MemoPolicy {
+            required: true,
+            format: MemoFormat::Numeric { max_digits: 10 },
+        };
+        let mut contract = setup(&mut context, policy);
+        contract.ft_transfer(accounts(2), 10.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INVALID_MEMO (1001)")]
+    fn test_optional_memo_format() {
+        let mut context = get_context(accounts(1));
+        let policy = MemoPolicy {
+            required: false,
+            format: MemoFormat::JsonObject,
+        };
+        let mut contract = setup(&mut context, policy);
+        contract.ft_transfer(accounts(2), 10.into(), None);
+        contract.ft_transfer(
+            accounts(2),
+            10.into(),
+            Some(r#"{"originator":"alice"}"#.to_string()),
+        );
+        contract.ft_transfer(accounts(2), 10.into(), Some("alice".to_string()));
+    }
+}
@@ -0,0 +1,131 @@
+use crate::*;
+
+/// NEP-148 expects the icon to be a data URL, e.g. `data:image/svg+xml,...` or
+/// `data:image/png;base64,...`.
+fn assert_valid_icon(icon: &str) {
+    let (header, data) = match icon
+        .strip_prefix("data:image/")
+        .and_then(|rest| rest.split_once(','))
+    {
+        Some(parts) => parts,
+        None => ContractError::InvalidIcon.panic(),
+    };
+    if header.is_empty() || data.is_empty() {
+        ContractError::InvalidIcon.panic();
+    }
+    if header.ends_with(";base64")
+        && !data
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'+' || c == b'/' || c == b'=')
+    {
+        ContractError::InvalidIcon.panic();
+    }
+}
+
+impl Contract {
+    /// Validates and stores the metadata, bumping `metadata_version` so wallets know their
+    /// cached copy is stale.
+    pub(crate) fn internal_set_metadata(&mut self, metadata: FungibleTokenMetadata) {
+        metadata.assert_valid();
+        if let Some(icon) = &metadata.icon {
+            assert_valid_icon(icon);
+        }
+        if let Some(current) = self.metadata.get() {
+            if current.decimals != metadata.decimals {
+                ContractError::DecimalsChange.panic();
+            }
+        }
+        self.metadata.set(&metadata);
+        self.metadata_version += 1;
+        event::emit::metadata_update(self.metadata_version);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Replaces the whole metadata, including `reference` and `reference_hash`.
+    /// `decimals` can't be changed once set.
+    pub fn update_metadata(&mut self, metadata: FungibleTokenMetadata) {
+        self.abort_if_not_owner();
+        self.internal_set_metadata(metadata);
+    }
+
+    /// Incremented on every metadata change, starting from 1 for the initial metadata.
+    pub fn metadata_version(&self) -> u64 {
+        self.metadata_version
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::json_types::Base64VecU8;
+    use near_sdk::test_utils::{accounts, get_logs};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::get_context;
+
+    fn metadata() -> FungibleTokenMetadata {
+        FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.to_string(),
+            name: "Tether Gold".to_string(),
+            symbol: "XAUt".to_string(),
+            icon: Some("data:image/png;base64,iVBORw0KGgo=".to_string()),
+            reference: Some("https://tether.to/xaut.json".to_string()),
+            reference_hash: Some(Base64VecU8(vec![7; 32])),
+            decimals: 6,
+        }
+    }
+
+    #[test]
+    fn test_update_metadata() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        assert_eq!(contract.metadata_version(), 1);
+
+        contract.update_metadata(metadata());
+        assert_eq!(contract.metadata_version(), 2);
+        let stored = contract.ft_metadata();
+        assert_eq!(stored.symbol, "XAUt");
+        assert_eq!(stored.reference, metadata().reference);
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"tether_token","version":"1.0.0","event":"metadata_update","data":[{"metadata_version":2}]}"#
+        );
+
+        contract.upgrade_name_symbol("Tether USD".to_string(), "USDt".to_string());
+        assert_eq!(contract.metadata_version(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "Hash has to be 32 bytes")]
+    fn test_update_metadata_invalid_reference_hash() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        let mut metadata = metadata();
+        metadata.reference_hash = Some(Base64VecU8(vec![7; 16]));
+        contract.update_metadata(metadata);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INVALID_ICON")]
+    fn test_update_metadata_invalid_icon() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.upgrade_icon("https://tether.to/usdt.svg".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_DECIMALS_CHANGE")]
+    fn test_update_metadata_decimals() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        let mut metadata = metadata();
+        metadata.decimals = 18;
+        contract.update_metadata(metadata);
+    }
+}
@@ -0,0 +1,509 @@
+use crate::*;
+use near_sdk::collections::Vector;
+
+/// Storage key of the layout tag. It lives outside of the `STATE` record so it can be read
+/// before we know how to deserialize the contract itself.
+const STATE_VERSION_KEY: &[u8] = b"v";
+
+/// Layout versions of the `Contract` state. Contracts deployed before the tag existed have no
+/// record under `STATE_VERSION_KEY` and are treated as `V1`.
+#[derive(
+    BorshDeserialize,
+    BorshSerialize,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    PartialOrd,
+    Ord,
+    Debug,
+    Serialize,
+    Deserialize,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub enum StateVersion {
+    V1,
+    V2,
+}
+
+impl StateVersion {
+    /// The layout of the `Contract` struct compiled into this binary.
+    pub const CURRENT: StateVersion = StateVersion::V2;
+
+    pub(crate) fn read() -> Self {
+        match env::storage_read(STATE_VERSION_KEY) {
+            Some(bytes) => StateVersion::try_from_slice(&bytes)
+                .unwrap_or_else(|_| ContractError::UnknownStateVersion.panic()),
+            None => StateVersion::V1,
+        }
+    }
+
+    pub(crate) fn write(self) {
+        env::storage_write(
+            STATE_VERSION_KEY,
+            &self
+                .try_to_vec()
+                .expect("Unable to serialize state version"),
+        );
+    }
+}
+
+/// Layout of the contract up to and including `tether_token:0.1.1`.
+/// Never change this struct: it describes bytes that are already on chain.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct ContractV1 {
+    pub owner_id: AccountId,
+    pub proposed_owner_id: AccountId,
+    pub token: FungibleToken,
+    pub metadata: LazyOption<FungibleTokenMetadata>,
+    pub guardians: UnorderedSet<AccountId>,
+    pub black_list: LookupMap<AccountId, BlackListStatus>,
+    pub status: ContractStatus,
+}
+
+impl From<ContractV1> for Contract {
+    fn from(v1: ContractV1) -> Self {
+        Self {
+            owner_id: v1.owner_id,
+            proposed_owner_id: v1.proposed_owner_id,
+            token: v1.token,
+            metadata: v1.metadata,
+            guardians: v1.guardians,
+            black_list: v1.black_list,
+            status: v1.status,
+            migration: None,
+            // The metadata set at initialization is the first version.
+            metadata_version: 1,
+            roles: UnorderedMap::new(b"o".to_vec()),
+            supply_cap: None,
+            blacklist_mode: BlackListMode::Sender,
+            snapshots: Snapshots::new(),
+            holders: UnorderedSet::new(b"d".to_vec()),
+            transfer_fee: None,
+            fee_exemptions: UnorderedSet::new(b"f".to_vec()),
+            limits: TransferLimits::new(),
+            memo_policies: LookupMap::new(b"e".to_vec()),
+            incoming_policies: LookupMap::new(b"i".to_vec()),
+            recoveries: Vector::new(b"g".to_vec()),
+            sponsorship: SponsoredStorage::new(),
+            force_close_allowed: true,
+            audit: None,
+            bridge: Bridge::new(),
+        }
+    }
+}
+
+/// Everything added after `V1` (roles, snapshots, policies, the holder index, ...) is left in
+/// storage, where the old binary doesn't look. The holder index goes stale, which is why
+/// `migrate` rebuilds it on the next upgrade.
+impl From<Contract> for ContractV1 {
+    fn from(contract: Contract) -> Self {
+        Self {
+            owner_id: contract.owner_id,
+            proposed_owner_id: contract.proposed_owner_id,
+            token: contract.token,
+            metadata: contract.metadata,
+            guardians: contract.guardians,
+            black_list: contract.black_list,
+            status: contract.status,
+        }
+    }
+}
+
+/// The state in the layout of any version, so migrations can be applied one step at a time.
+enum VersionedContract {
+    V1(ContractV1),
+    V2(Box<Contract>),
+}
+
+impl VersionedContract {
+    fn read(state_version: StateVersion) -> Self {
+        match state_version {
+            StateVersion::V1 => VersionedContract::V1(read_state()),
+            StateVersion::V2 => VersionedContract::V2(Box::new(read_state())),
+        }
+    }
+
+    fn write(&self) {
+        match self {
+            VersionedContract::V1(state) => env::state_write(state),
+            VersionedContract::V2(state) => env::state_write(state),
+        }
+    }
+
+    fn version(&self) -> StateVersion {
+        match self {
+            VersionedContract::V1(_) => StateVersion::V1,
+            VersionedContract::V2(_) => StateVersion::V2,
+        }
+    }
+
+    /// Applies the migration to the next version.
+    fn upgrade(self) -> Self {
+        match self {
+            VersionedContract::V1(state) => VersionedContract::V2(Box::new(state.into())),
+            VersionedContract::V2(_) => ContractError::UnknownStateVersion.panic(),
+        }
+    }
+
+    /// Undoes the migration from the previous version.
+    fn downgrade(self) -> Self {
+        match self {
+            VersionedContract::V1(_) => ContractError::UnknownStateVersion.panic(),
+            VersionedContract::V2(state) => VersionedContract::V1((*state).into()),
+        }
+    }
+}
+
+fn read_state<T: BorshDeserialize>() -> T {
+    env::state_read().expect("Contract is not initialized.")
+}
+
+/// Reads the state stored by any previous version and brings it up to `StateVersion::CURRENT`,
+/// applying every intermediate migration in order.
+pub(crate) fn migrate_state() -> Contract {
+    let mut state = VersionedContract::read(StateVersion::read());
+    while state.version() < StateVersion::CURRENT {
+        state = state.upgrade();
+    }
+    let contract = match state {
+        VersionedContract::V2(contract) => *contract,
+        _ => ContractError::UnknownStateVersion.panic(),
+    };
+    StateVersion::CURRENT.write();
+    contract
+}
+
+/// Writes the state in the layout of an older binary, undoing the migrations in reverse order.
+/// Used by `rollback()` right before the older code is deployed.
+pub(crate) fn downgrade_state(contract: Contract, state_version: StateVersion) {
+    if contract.migration.is_some() {
+        ContractError::MigrationInProgress.panic();
+    }
+    let mut state = VersionedContract::V2(Box::new(contract));
+    while state.version() > state_version {
+        state = state.downgrade();
+    }
+    state.write();
+    if state_version == StateVersion::V1 {
+        // Binaries of the first layout don't know about the tag.
+        env::storage_remove(STATE_VERSION_KEY);
+    } else {
+        state_version.write();
+    }
+}
+
+/// Per-account data migrations that are too large for a single `migrate` call.
+#[derive(
+    BorshDeserialize, BorshSerialize, Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub enum MigrationTask {
+    /// Drops `Allowable` records that `remove_from_blacklist` leaves in `black_list`.
+    PruneBlackList,
+    /// Adds accounts with a non-zero balance to the `ft_holders` index.
+    IndexHolders,
+}
+
+/// A chunked migration in flight. `LookupMap`s can't be enumerated on chain, so the owner
+/// queues the affected accounts and `migrate_step` pops them off in batches; the queue length
+/// is the persisted cursor.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct MigrationProgress {
+    task: MigrationTask,
+    queue: Vector<AccountId>,
+    processed: u64,
+    resume_status: ContractStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MigrationProgressView {
+    pub task: MigrationTask,
+    pub processed: u64,
+    pub remaining: u64,
+}
+
+impl From<&MigrationProgress> for MigrationProgressView {
+    fn from(progress: &MigrationProgress) -> Self {
+        Self {
+            task: progress.task,
+            processed: progress.processed,
+            remaining: progress.queue.len(),
+        }
+    }
+}
+
+impl Contract {
+    /// Switches the contract into `Migrating` status. Can be called from `migrate_state` when a
+    /// new layout needs a chunked migration, or by the owner through `start_migration`.
+    pub(crate) fn internal_start_migration(&mut self, task: MigrationTask) {
+        if self.migration.is_some() {
+            ContractError::MigrationInProgress.panic();
+        }
+        self.migration = Some(MigrationProgress {
+            task,
+            queue: Vector::new(b"q".to_vec()),
+            processed: 0,
+            resume_status: self.status,
+        });
+        self.status = ContractStatus::Migrating;
+    }
+
+    fn migration_mut(&mut self) -> &mut MigrationProgress {
+        self.migration
+            .as_mut()
+            .unwrap_or_else(|| ContractError::NoMigration.panic())
+    }
+
+    fn migrate_account(&mut self, task: MigrationTask, account_id: &AccountId) {
+        match task {
+            MigrationTask::PruneBlackList => {
+                if self.black_list.get(account_id) == Some(BlackListStatus::Allowable) {
+                    self.black_list.remove(account_id);
+                }
+            }
+            MigrationTask::IndexHolders => self.internal_sync_holder(account_id),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Should only be called by this contract on migration.
+    /// This method is called from `upgrade()` method.
+    /// For next version upgrades, add a new `StateVersion` variant, freeze the previous layout
+    /// in this module with `From` conversions to and from `Contract`, and add it to
+    /// `VersionedContract`.
+    ///
+    /// An upgrade from `V1` leaves the contract in `Migrating` status, with transfers blocked,
+    /// until the holder index is built: the owner has to queue every registered account (taken
+    /// from an indexer, the account map can't be enumerated on chain) with
+    /// `enqueue_migration_accounts`, then call `migrate_step` until the queue is empty and
+    /// `finish_migration`. Plan the upgrade window for the number of holders.
+    #[init(ignore_state)]
+    #[private]
+    pub fn migrate() -> Self {
+        let from = StateVersion::read();
+        let mut contract = migrate_state();
+        // Balances of older layouts are not in the holder index. The owner has to queue the
+        // registered accounts; holder views abort until `finish_migration`.
+        if from == StateVersion::V1 {
+            contract.internal_start_migration(MigrationTask::IndexHolders);
+        }
+        let mut registry = CodeRegistry::load();
+        registry.record_deployment();
+        registry.save();
+        contract
+    }
+
+    pub fn state_version(&self) -> StateVersion {
+        StateVersion::read()
+    }
+
+    /// Starts a chunked migration. Transfers are blocked until `finish_migration`.
+    pub fn start_migration(&mut self, task: MigrationTask) {
+        self.abort_if_not_owner();
+        self.internal_start_migration(task);
+    }
+
+    /// Adds accounts to the queue of the running migration.
+    pub fn enqueue_migration_accounts(&mut self, account_ids: Vec<AccountId>) {
+        self.abort_if_not_owner();
+        let progress = self.migration_mut();
+        for account_id in account_ids {
+            progress.queue.push(&account_id);
+        }
+    }
+
+    /// Processes up to `limit` queued accounts. Call repeatedly until `remaining` is zero.
+    pub fn migrate_step(&mut self, limit: u64) -> MigrationProgressView {
+        self.abort_if_not_owner();
+        let task = self.migration_mut().task;
+        for _ in 0..limit {
+            let account_id = match self.migration_mut().queue.pop() {
+                Some(account_id) => account_id,
+                None => break,
+            };
+            self.migrate_account(task, &account_id);
+            self.migration_mut().processed += 1;
+        }
+        MigrationProgressView::from(&*self.migration_mut())
+    }
+
+    /// Leaves `Migrating` status once the queue is drained, restoring the previous status.
+    pub fn finish_migration(&mut self) {
+        self.abort_if_not_owner();
+        let progress = self.migration_mut();
+        if !progress.queue.is_empty() {
+            ContractError::MigrationQueueNotEmpty.panic();
+        }
+        let resume_status = progress.resume_status;
+        let view = MigrationProgressView::from(&*progress);
+        self.migration = None;
+        self.status = resume_status;
+        log!(
+            "Migration {:?} finished, {} accounts processed",
+            view.task,
+            view.processed
+        );
+    }
+
+    pub fn migration_progress(&self) -> Option<MigrationProgressView> {
+        self.migration.as_ref().map(MigrationProgressView::from)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::get_context;
+
+    fn write_v1_state(owner_id: &AccountId) {
+        let mut v1 = ContractV1 {
+            owner_id: owner_id.clone(),
+            proposed_owner_id: owner_id.clone(),
+            token: FungibleToken::new(b"a".to_vec()),
+            metadata: LazyOption::new(b"m".to_vec(), None),
+            guardians: UnorderedSet::new(b"c".to_vec()),
+            black_list: LookupMap::new(b"b".to_vec()),
+            status: ContractStatus::Paused,
+        };
+        v1.token.internal_register_account(owner_id);
+        v1.token.internal_deposit(owner_id, 1_000);
+        v1.guardians.insert(&accounts(2));
+        v1.black_list.insert(&accounts(3), &BlackListStatus::Banned);
+        env::state_write(&v1);
+    }
+
+    #[test]
+    fn test_migrate_from_v1() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        write_v1_state(&accounts(1));
+        assert!(env::storage_read(STATE_VERSION_KEY).is_none());
+
+        let mut contract = Contract::migrate();
+        assert_eq!(contract.state_version(), StateVersion::CURRENT);
+        assert_eq!(contract.owner(), accounts(1));
+        assert_eq!(contract.guardians(), vec![accounts(2)]);
+        assert_eq!(contract.token.total_supply, 1_000);
+        assert_eq!(
+            contract.black_list.get(&accounts(3)),
+            Some(BlackListStatus::Banned)
+        );
+        assert_eq!(contract.metadata_version(), 1);
+
+        // The holder index is built before the contract goes back to its previous status.
+        assert_eq!(contract.contract_status(), ContractStatus::Migrating);
+        assert_eq!(
+            contract.migration_progress().unwrap().task,
+            MigrationTask::IndexHolders
+        );
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.enqueue_migration_accounts(vec![accounts(1)]);
+        contract.migrate_step(10);
+        contract.finish_migration();
+        assert_eq!(contract.contract_status(), ContractStatus::Paused);
+        assert_eq!(contract.holders.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_MIGRATING")]
+    fn test_holder_views_abort_until_indexed() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        write_v1_state(&accounts(1));
+        let contract = Contract::migrate();
+        contract.ft_holder_count();
+    }
+
+    #[test]
+    fn test_migrate_current_state() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        assert_eq!(contract.state_version(), StateVersion::CURRENT);
+        env::state_write(&contract);
+
+        let migrated = Contract::migrate();
+        assert_eq!(migrated.state_version(), StateVersion::CURRENT);
+        assert_eq!(migrated.ft_total_supply().0, 1_000);
+    }
+
+    #[test]
+    fn test_chunked_migration() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        for account_id in [accounts(2), accounts(3), accounts(4)].iter() {
+            contract.add_to_blacklist(account_id);
+            contract.remove_from_blacklist(account_id);
+        }
+        contract.add_to_blacklist(&accounts(5));
+
+        contract.start_migration(MigrationTask::PruneBlackList);
+        assert_eq!(contract.contract_status(), ContractStatus::Migrating);
+        contract.enqueue_migration_accounts(vec![
+            accounts(2),
+            accounts(3),
+            accounts(4),
+            accounts(5),
+        ]);
+
+        let progress = contract.migrate_step(3);
+        assert_eq!(progress.processed, 3);
+        assert_eq!(progress.remaining, 1);
+        let progress = contract.migrate_step(3);
+        assert_eq!(progress.processed, 4);
+        assert_eq!(progress.remaining, 0);
+
+        contract.finish_migration();
+        assert_eq!(contract.contract_status(), ContractStatus::Working);
+        assert!(contract.migration_progress().is_none());
+        assert!(contract.black_list.get(&accounts(2)).is_none());
+        assert!(contract.black_list.get(&accounts(4)).is_none());
+        assert_eq!(
+            contract.black_list.get(&accounts(5)),
+            Some(BlackListStatus::Banned)
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_total_supply();
+    }
+
+    #[test]
+    #[should_panic(expected = "Operation aborted because the contract state is being migrated")]
+    fn test_transfer_blocked_during_migration() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.start_migration(MigrationTask::PruneBlackList);
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(2), 10.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Migration queue is not empty")]
+    fn test_finish_migration_with_pending_queue() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.start_migration(MigrationTask::PruneBlackList);
+        contract.enqueue_migration_accounts(vec![accounts(2)]);
+        contract.finish_migration();
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown contract state version")]
+    fn test_migrate_unknown_version() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        write_v1_state(&accounts(1));
+        env::storage_write(STATE_VERSION_KEY, &[u8::MAX]);
+        Contract::migrate();
+    }
+}
@@ -0,0 +1,124 @@
+use crate::*;
+
+/// What an account accepts as a receiver. Contracts that don't implement `ft_on_transfer` can
+/// use `CallOnly` so plain transfers can't lock tokens on them.
+#[derive(
+    BorshDeserialize,
+    BorshSerialize,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    Debug,
+    Default,
+    Serialize,
+    Deserialize,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub enum IncomingPolicy {
+    #[default]
+    Any,
+    /// Only `ft_transfer_call` is accepted.
+    CallOnly,
+    /// Neither `ft_transfer` nor `ft_transfer_call` is accepted.
+    RejectAll,
+}
+
+impl Contract {
+    pub(crate) fn abort_if_incoming_rejected(&self, receiver_id: &AccountId, is_call: bool) {
+        match self.incoming_policies.get(receiver_id).unwrap_or_default() {
+            IncomingPolicy::Any => {}
+            IncomingPolicy::CallOnly if is_call => {}
+            IncomingPolicy::CallOnly => {
+                ContractError::ReceiverRequiresCall.panic_with(receiver_id.as_str())
+            }
+            IncomingPolicy::RejectAll => {
+                ContractError::ReceiverRejectsTransfers.panic_with(receiver_id.as_str())
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets what the caller accepts as a receiver. The storage is paid with the attached
+    /// deposit; going back to `Any` refunds it.
+    #[payable]
+    pub fn set_incoming_policy(&mut self, policy: IncomingPolicy) {
+        let account_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        match policy {
+            IncomingPolicy::Any => self.incoming_policies.remove(&account_id),
+            _ => self.incoming_policies.insert(&account_id, &policy),
+        };
+        self.internal_settle_storage(initial_storage_usage);
+    }
+
+    /// Lets wallets warn before sending to an account that won't accept the transfer.
+    pub fn incoming_policy_of(&self, account_id: AccountId) -> IncomingPolicy {
+        self.incoming_policies.get(&account_id).unwrap_or_default()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    use super::*;
+    use crate::test_utils;
+
+    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
+        let mut builder = test_utils::get_context(predecessor_account_id);
+        builder.prepaid_gas(Gas::ONE_TERA * 100);
+        builder
+    }
+
+    fn setup(context: &mut VMContextBuilder, policy: IncomingPolicy) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.token.internal_register_account(&accounts(2));
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.set_incoming_policy(policy);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract
+    }
+
+    #[test]
+    fn test_call_only() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup(&mut context, IncomingPolicy::CallOnly);
+        assert_eq!(
+            contract.incoming_policy_of(accounts(2)),
+            IncomingPolicy::CallOnly
+        );
+        assert_eq!(
+            contract.incoming_policy_of(accounts(3)),
+            IncomingPolicy::Any
+        );
+        contract.ft_transfer_call(accounts(2), 10.into(), None, String::new());
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_RECEIVER_REQUIRES_CALL (1100)")]
+    fn test_call_only_rejects_transfer() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup(&mut context, IncomingPolicy::CallOnly);
+        contract.ft_transfer(accounts(2), 10.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_RECEIVER_REJECTS_TRANSFERS (1101)")]
+    fn test_reject_all() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup(&mut context, IncomingPolicy::RejectAll);
+        contract.ft_transfer_call(accounts(2), 10.into(), None, String::new());
+    }
+}
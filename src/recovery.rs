@@ -0,0 +1,183 @@
+use crate::*;
+use near_sdk::json_types::U64;
+use near_sdk::serde_json::json;
+use near_sdk::PromiseResult;
+
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_RECOVERY_CALLBACK: Gas = Gas(10_000_000_000_000);
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RecoveryRecord {
+    /// This contract or the foreign NEP-141 token that was recovered.
+    pub token_id: AccountId,
+    pub amount: U128,
+    pub receiver_id: AccountId,
+    pub timestamp: U64,
+    pub block_height: U64,
+}
+
+impl Contract {
+    fn internal_record_recovery(&mut self, token_id: AccountId, amount: U128, to: AccountId) {
+        event::emit::tokens_recovered(&token_id, amount.0, &to);
+        self.recoveries.push(&RecoveryRecord {
+            token_id,
+            amount,
+            receiver_id: to,
+            timestamp: env::block_timestamp().into(),
+            block_height: env::block_height().into(),
+        });
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Returns tokens that were sent to the token contract's own account. `account_id` is the
+    /// token to recover: this contract, or a foreign NEP-141 token the contract holds, which is
+    /// sent back with a cross-contract `ft_transfer` and recorded once it succeeds.
+    pub fn recover_tokens(
+        &mut self,
+        account_id: AccountId,
+        amount: U128,
+        to: AccountId,
+    ) -> PromiseOrValue<bool> {
+        self.abort_if_not_owner();
+        let contract_id = env::current_account_id();
+        if account_id == contract_id {
+            self.abort_if_pause();
+            self.internal_checkpoint_balance(&contract_id);
+            self.internal_checkpoint_balance(&to);
+            self.token.internal_transfer(
+                &contract_id,
+                &to,
+                amount.0,
+                Some("Recovered tokens".to_string()),
+            );
+            self.internal_sync_holder(&contract_id);
+            self.internal_sync_holder(&to);
+            self.internal_record_recovery(account_id, amount, to);
+            return PromiseOrValue::Value(true);
+        }
+
+        let transfer_args = json!({
+            "receiver_id": to,
+            "amount": amount,
+            "memo": "Recovered tokens",
+        });
+        let callback_args = json!({
+            "token_id": account_id,
+            "amount": amount,
+            "to": to,
+        });
+        Promise::new(account_id)
+            .function_call(
+                "ft_transfer".to_string(),
+                transfer_args.to_string().into_bytes(),
+                1,
+                GAS_FOR_FT_TRANSFER,
+            )
+            .then(Promise::new(contract_id).function_call(
+                "on_tokens_recovered".to_string(),
+                callback_args.to_string().into_bytes(),
+                0,
+                GAS_FOR_RECOVERY_CALLBACK,
+            ))
+            .into()
+    }
+
+    #[private]
+    pub fn on_tokens_recovered(
+        &mut self,
+        token_id: AccountId,
+        amount: U128,
+        to: AccountId,
+    ) -> bool {
+        let success = env::promise_results_count() == 1
+            && matches!(env::promise_result(0), PromiseResult::Successful(_));
+        if success {
+            self.internal_record_recovery(token_id, amount, to);
+        } else {
+            log!("Failed to recover {} of {}", amount.0, token_id);
+        }
+        success
+    }
+
+    /// Log of the recoveries, with pagination.
+    pub fn recoveries(&self, from_index: u64, limit: u64) -> Vec<RecoveryRecord> {
+        self.recoveries
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+
+    use super::*;
+    use crate::test_utils;
+
+    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
+        let mut builder = test_utils::get_context(predecessor_account_id);
+        builder.prepaid_gas(Gas::ONE_TERA * 100);
+        builder
+    }
+
+    #[test]
+    fn test_recover_own_tokens() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.token.internal_register_account(&accounts(0));
+        contract.token.internal_register_account(&accounts(2));
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(0), 300.into(), None);
+
+        testing_env!(context.attached_deposit(0).build());
+        contract.recover_tokens(accounts(0), 300.into(), accounts(2));
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 300);
+        let recoveries = contract.recoveries(0, 10);
+        assert_eq!(recoveries.len(), 1);
+        assert_eq!(recoveries[0].token_id, accounts(0));
+        assert_eq!(recoveries[0].receiver_id, accounts(2));
+    }
+
+    #[test]
+    fn test_recover_foreign_tokens() {
+        let context = get_context(accounts(0));
+        testing_env!(
+            context.build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        assert!(!contract.on_tokens_recovered(accounts(3), 5.into(), accounts(2)));
+        assert!(contract.recoveries(0, 10).is_empty());
+
+        testing_env!(
+            context.build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])]
+        );
+        assert!(contract.on_tokens_recovered(accounts(3), 5.into(), accounts(2)));
+        assert_eq!(contract.recoveries(0, 10)[0].token_id, accounts(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_OWNER (100)")]
+    fn test_recover_by_user() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.recover_tokens(accounts(3), 5.into(), accounts(2));
+    }
+}
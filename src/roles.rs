@@ -0,0 +1,116 @@
+use crate::*;
+
+/// Permissions the owner can delegate to other accounts. The owner implicitly has all of them.
+#[derive(
+    BorshDeserialize, BorshSerialize, Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Role {
+    /// Can `mint` and `burn`.
+    Minter,
+    /// Can `add_to_blacklist` and `remove_from_blacklist`.
+    Blacklister,
+    /// Can call `upgrade()`. Granted to the factory that deployed the instance.
+    Upgrader,
+    /// Can `take_snapshot`, e.g. the account that takes the scheduled end-of-day snapshots.
+    Snapshotter,
+    /// Can set the transfer limits and reset the circuit breaker.
+    Compliance,
+    /// Can `bridge_mint` tokens locked on other chains.
+    Bridge,
+}
+
+impl Contract {
+    pub(crate) fn internal_grant_role(&mut self, account_id: &AccountId, role: Role) {
+        let mut roles = self.roles.get(account_id).unwrap_or_default();
+        if !roles.contains(&role) {
+            roles.push(role);
+            self.roles.insert(account_id, &roles);
+        }
+    }
+
+    pub(crate) fn has_role(&self, account_id: &AccountId, role: Role) -> bool {
+        self.roles
+            .get(account_id)
+            .unwrap_or_default()
+            .contains(&role)
+    }
+
+    pub(crate) fn abort_if_not_owner_or_role(&self, role: Role) {
+        let predecessor = env::predecessor_account_id();
+        if predecessor != self.owner_id && !self.has_role(&predecessor, role) {
+            ContractError::MissingRole.panic_with(&format!("{:?}", role));
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn grant_role(&mut self, account_id: AccountId, role: Role) {
+        self.abort_if_not_owner();
+        self.internal_grant_role(&account_id, role);
+    }
+
+    pub fn revoke_role(&mut self, account_id: AccountId, role: Role) {
+        self.abort_if_not_owner();
+        if let Some(mut roles) = self.roles.get(&account_id) {
+            roles.retain(|r| *r != role);
+            if roles.is_empty() {
+                self.roles.remove(&account_id);
+            } else {
+                self.roles.insert(&account_id, &roles);
+            }
+        }
+    }
+
+    pub fn roles_of(&self, account_id: AccountId) -> Vec<Role> {
+        self.roles.get(&account_id).unwrap_or_default()
+    }
+
+    /// Accounts with at least one role, with pagination.
+    pub fn role_members(&self, from_index: u64, limit: u64) -> Vec<(AccountId, Vec<Role>)> {
+        self.roles
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::get_context;
+
+    #[test]
+    fn test_minter_role() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.grant_role(accounts(2), Role::Minter);
+        assert_eq!(contract.roles_of(accounts(2)), vec![Role::Minter]);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.mint(&accounts(1), 500.into());
+        assert_eq!(contract.ft_total_supply().0, 1_500);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.revoke_role(accounts(2), Role::Minter);
+        assert!(contract.roles_of(accounts(2)).is_empty());
+        assert!(contract.role_members(0, 10).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_MISSING_ROLE (105): This method requires a role: Blacklister")]
+    fn test_blacklist_without_role() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.grant_role(accounts(2), Role::Minter);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.add_to_blacklist(&accounts(3));
+    }
+}
@@ -0,0 +1,245 @@
+use crate::*;
+use near_sdk::collections::Vector;
+use near_sdk::json_types::U64;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SnapshotInfo {
+    pub snapshot_id: U64,
+    pub block_height: U64,
+    pub timestamp: U64,
+}
+
+/// Historical balances. Values are checkpointed lazily: the first write to a balance after a
+/// snapshot stores the value it had before the write, tagged with the latest snapshot id.
+/// The balance at snapshot `n` is then the first checkpoint tagged `n` or later, or the current
+/// balance if there is none.
+///
+/// Checkpoints are kept in a `Vector` per account and one for the total supply, so a write
+/// touches one entry and a lookup reads O(log n) of them.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Snapshots {
+    snapshots: Vector<SnapshotInfo>,
+    balances: LookupMap<AccountId, Vector<(u64, Balance)>>,
+    total_supply: Vector<(u64, Balance)>,
+}
+
+impl Snapshots {
+    pub(crate) fn new() -> Self {
+        Self {
+            snapshots: Vector::new(b"n".to_vec()),
+            balances: LookupMap::new(b"p".to_vec()),
+            total_supply: Vector::new(b"S".to_vec()),
+        }
+    }
+
+    /// Id of the latest snapshot, 0 if none was taken yet.
+    fn current_id(&self) -> u64 {
+        self.snapshots.len()
+    }
+
+    fn checkpoint(
+        checkpoints: &mut Vector<(u64, Balance)>,
+        current_id: u64,
+        value: Balance,
+    ) -> bool {
+        let last = checkpoints
+            .len()
+            .checked_sub(1)
+            .and_then(|index| checkpoints.get(index));
+        match last {
+            Some((id, _)) if id == current_id => false,
+            _ => {
+                checkpoints.push(&(current_id, value));
+                true
+            }
+        }
+    }
+
+    /// First checkpoint tagged `snapshot_id` or later.
+    fn value_at(checkpoints: &Vector<(u64, Balance)>, snapshot_id: u64) -> Option<Balance> {
+        let (mut low, mut high) = (0, checkpoints.len());
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match checkpoints.get(middle) {
+                Some((id, _)) if id < snapshot_id => low = middle + 1,
+                _ => high = middle,
+            }
+        }
+        checkpoints.get(low).map(|(_, value)| value)
+    }
+
+    fn balance_at(&self, account_id: &AccountId, snapshot_id: u64) -> Option<Balance> {
+        self.balances
+            .get(account_id)
+            .and_then(|checkpoints| Self::value_at(&checkpoints, snapshot_id))
+    }
+
+    fn checkpoint_balance(&mut self, account_id: &AccountId, value: Balance) {
+        let current_id = self.current_id();
+        let mut checkpoints = self.balances.get(account_id).unwrap_or_else(|| {
+            let mut prefix = b"Q".to_vec();
+            prefix.extend(env::sha256(account_id.as_bytes()));
+            Vector::new(prefix)
+        });
+        // The `Vector` length is stored in the map entry.
+        if Self::checkpoint(&mut checkpoints, current_id, value) {
+            self.balances.insert(account_id, &checkpoints);
+        }
+    }
+}
+
+impl Contract {
+    /// Must be called before the balance of `account_id` changes.
+    pub(crate) fn internal_checkpoint_balance(&mut self, account_id: &AccountId) {
+        let balance = self.token.accounts.get(account_id).unwrap_or(0);
+        self.internal_checkpoint_balance_with(account_id, balance);
+    }
+
+    /// Same as `internal_checkpoint_balance`, for callers that already changed the balance and
+    /// know the previous value.
+    pub(crate) fn internal_checkpoint_balance_with(
+        &mut self,
+        account_id: &AccountId,
+        balance: Balance,
+    ) {
+        if self.snapshots.current_id() == 0 {
+            return;
+        }
+        self.snapshots.checkpoint_balance(account_id, balance);
+    }
+
+    /// Must be called before the total supply changes.
+    pub(crate) fn internal_checkpoint_supply(&mut self) {
+        let total_supply = self.token.total_supply;
+        self.internal_checkpoint_supply_with(total_supply);
+    }
+
+    pub(crate) fn internal_checkpoint_supply_with(&mut self, total_supply: Balance) {
+        let current_id = self.snapshots.current_id();
+        if current_id == 0 {
+            return;
+        }
+        Snapshots::checkpoint(&mut self.snapshots.total_supply, current_id, total_supply);
+    }
+
+    fn abort_if_unknown_snapshot(&self, snapshot_id: u64) {
+        if snapshot_id == 0 || snapshot_id > self.snapshots.current_id() {
+            ContractError::UnknownSnapshot.panic_with(&snapshot_id.to_string());
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Records the current block as a new snapshot and returns its id. Balances at this point
+    /// can be read with `ft_balance_of_at` and `ft_total_supply_at`.
+    pub fn take_snapshot(&mut self) -> U64 {
+        self.abort_if_not_owner_or_role(Role::Snapshotter);
+        let info = SnapshotInfo {
+            snapshot_id: (self.snapshots.current_id() + 1).into(),
+            block_height: env::block_height().into(),
+            timestamp: env::block_timestamp().into(),
+        };
+        self.snapshots.snapshots.push(&info);
+        event::emit::snapshot(info.snapshot_id.0, info.block_height.0);
+        info.snapshot_id
+    }
+
+    pub fn current_snapshot_id(&self) -> U64 {
+        self.snapshots.current_id().into()
+    }
+
+    pub fn snapshot(&self, snapshot_id: U64) -> Option<SnapshotInfo> {
+        match snapshot_id.0 {
+            0 => None,
+            id => self.snapshots.snapshots.get(id - 1),
+        }
+    }
+
+    pub fn ft_balance_of_at(&self, account_id: AccountId, snapshot_id: U64) -> U128 {
+        self.abort_if_pause();
+        self.abort_if_unknown_snapshot(snapshot_id.0);
+        self.snapshots
+            .balance_at(&account_id, snapshot_id.0)
+            .unwrap_or_else(|| self.token.accounts.get(&account_id).unwrap_or(0))
+            .into()
+    }
+
+    pub fn ft_total_supply_at(&self, snapshot_id: U64) -> U128 {
+        self.abort_if_pause();
+        self.abort_if_unknown_snapshot(snapshot_id.0);
+        Snapshots::value_at(&self.snapshots.total_supply, snapshot_id.0)
+            .unwrap_or(self.token.total_supply)
+            .into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::get_context;
+
+    #[test]
+    fn test_snapshots() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.token.internal_register_account(&accounts(2));
+
+        let first = contract.take_snapshot();
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(2), 300.into(), None);
+        testing_env!(context.attached_deposit(0).build());
+        contract.mint(&accounts(2), 500.into());
+
+        let second = contract.take_snapshot();
+        contract.burn(&accounts(1), 100.into());
+        // A snapshot without writes in between shares the checkpoints of the next one.
+        let third = contract.take_snapshot();
+        contract.burn(&accounts(2), 200.into());
+
+        assert_eq!(contract.ft_balance_of_at(accounts(1), first).0, 1_000);
+        assert_eq!(contract.ft_balance_of_at(accounts(2), first).0, 0);
+        assert_eq!(contract.ft_total_supply_at(first).0, 1_000);
+
+        assert_eq!(contract.ft_balance_of_at(accounts(1), second).0, 700);
+        assert_eq!(contract.ft_balance_of_at(accounts(2), second).0, 800);
+        assert_eq!(contract.ft_total_supply_at(second).0, 1_500);
+
+        assert_eq!(contract.ft_balance_of_at(accounts(1), third).0, 600);
+        assert_eq!(contract.ft_balance_of_at(accounts(2), third).0, 800);
+        assert_eq!(contract.ft_total_supply_at(third).0, 1_400);
+
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 600);
+        assert_eq!(contract.current_snapshot_id(), third);
+    }
+
+    #[test]
+    fn test_snapshotter_role() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.grant_role(accounts(2), Role::Snapshotter);
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_index(42)
+            .build());
+        let snapshot_id = contract.take_snapshot();
+        assert_eq!(snapshot_id.0, 1);
+        assert_eq!(contract.snapshot(snapshot_id).unwrap().block_height.0, 42);
+        assert!(contract.snapshot(2.into()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_UNKNOWN_SNAPSHOT (700): The snapshot doesn't exist: 1")]
+    fn test_unknown_snapshot() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.ft_total_supply_at(1.into());
+    }
+}
@@ -0,0 +1,487 @@
+use crate::views::abort_if_batch_too_large;
+use crate::*;
+use near_contract_standards::storage_management::{StorageBalance, StorageBalanceBounds};
+use near_sdk::assert_one_yocto;
+
+const DAY: u64 = 86_400_000_000_000;
+/// Registrations per sender and day that the global pool pays for.
+const GLOBAL_SPONSORED_PER_DAY: u32 = 10;
+
+/// Registration of an account whose storage was paid by a sponsor pool.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Sponsorship {
+    /// Owner of the pool that paid, `None` for the global pool.
+    pub sponsor_id: Option<AccountId>,
+    /// Returned to the pool when the account unregisters.
+    pub deposit: U128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct SponsoredStorage {
+    pools: LookupMap<AccountId, Balance>,
+    global_pool: Balance,
+    accounts: LookupMap<AccountId, Sponsorship>,
+    /// Registrations paid by the global pool per sender, as `(day, count)`.
+    global_usage: LookupMap<AccountId, (u64, u32)>,
+}
+
+impl SponsoredStorage {
+    pub(crate) fn new() -> Self {
+        Self {
+            pools: LookupMap::new(b"j".to_vec()),
+            global_pool: 0,
+            accounts: LookupMap::new(b"x".to_vec()),
+            global_usage: LookupMap::new(b"t".to_vec()),
+        }
+    }
+
+    /// Counts a registration that the global pool pays for `sender_id`, so a single sender
+    /// can't drain the pool.
+    fn count_global_registration(&mut self, sender_id: &AccountId) {
+        let day = env::block_timestamp() / DAY;
+        let count = match self.global_usage.get(sender_id) {
+            Some((last_day, count)) if last_day == day => count,
+            _ => 0,
+        };
+        if count >= GLOBAL_SPONSORED_PER_DAY {
+            ContractError::SponsorLimitExceeded.panic_with(sender_id.as_str());
+        }
+        self.global_usage.insert(sender_id, &(day, count + 1));
+    }
+
+    fn credit(&mut self, sponsor_id: Option<&AccountId>, amount: Balance) {
+        match sponsor_id {
+            Some(sponsor_id) => {
+                let pool = self.pools.get(sponsor_id).unwrap_or(0);
+                self.pools.insert(sponsor_id, &(pool + amount));
+            }
+            None => self.global_pool += amount,
+        }
+    }
+}
+
+impl Contract {
+    /// Registers `receiver_id` on a sponsor's account: the pool of `sender_id` if it covers the
+    /// storage, the global pool otherwise. Nothing happens when the receiver is registered or
+    /// no pool is funded, so the transfer fails as usual.
+    pub(crate) fn internal_sponsor_registration(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+    ) {
+        if self.token.accounts.contains_key(receiver_id) {
+            return;
+        }
+        let sender_pool = self.sponsorship.pools.get(sender_id);
+        if sender_pool.is_none() && self.sponsorship.global_pool == 0 {
+            return;
+        }
+
+        let initial_storage_usage = env::storage_usage();
+        self.token.internal_register_account(receiver_id);
+        let mut sponsorship = Sponsorship {
+            sponsor_id: None,
+            deposit: 0.into(),
+        };
+        self.sponsorship.accounts.insert(receiver_id, &sponsorship);
+        let cost =
+            Balance::from(env::storage_usage() - initial_storage_usage) * env::storage_byte_cost();
+
+        match sender_pool {
+            Some(pool) if pool >= cost => {
+                self.sponsorship.pools.insert(sender_id, &(pool - cost));
+                sponsorship.sponsor_id = Some(sender_id.clone());
+            }
+            _ if self.sponsorship.global_pool >= cost => {
+                self.sponsorship.count_global_registration(sender_id);
+                self.sponsorship.global_pool -= cost;
+            }
+            _ => ContractError::InsufficientSponsorPool.panic_with(receiver_id.as_str()),
+        }
+        sponsorship.deposit = cost.into();
+        self.sponsorship.accounts.insert(receiver_id, &sponsorship);
+        log!("Registered @{} on a sponsor pool for {}", receiver_id, cost);
+    }
+
+    /// Same as `FungibleToken::internal_storage_unregister`, except that the storage of a
+    /// sponsored account is returned to the pool that paid it. Banned accounts can't close, so
+    /// they can't burn frozen funds to get the storage deposit back.
+    fn internal_storage_unregister(&mut self, force: Option<bool>) -> Option<(AccountId, Balance)> {
+        assert_one_yocto();
+        self.abort_if_pause();
+        let account_id = env::predecessor_account_id();
+        self.abort_if_blacklisted(&account_id);
+        let has_balance = self.token.accounts.get(&account_id).unwrap_or(0) > 0;
+        if has_balance && force.unwrap_or(false) && !self.force_close_allowed {
+            ContractError::ForceCloseDisabled.panic_with(account_id.as_str());
+        }
+        let sponsorship = match self.sponsorship.accounts.get(&account_id) {
+            Some(sponsorship) => sponsorship,
+            None => return self.token.internal_storage_unregister(force),
+        };
+        let balance = self.token.accounts.get(&account_id).unwrap_or(0);
+        if balance > 0 && !force.unwrap_or(false) {
+            ContractError::PositiveBalance.panic_with(account_id.as_str());
+        }
+        self.token.accounts.remove(&account_id);
+        self.token.total_supply -= balance;
+        self.sponsorship.accounts.remove(&account_id);
+        self.sponsorship
+            .credit(sponsorship.sponsor_id.as_ref(), sponsorship.deposit.0);
+        Promise::new(account_id.clone()).transfer(1);
+        Some((account_id, balance))
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Adds the attached deposit to the caller's sponsor pool, minus the storage of the pool.
+    #[payable]
+    pub fn sponsor_deposit(&mut self) -> U128 {
+        let sponsor_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        let pool = self.sponsorship.pools.get(&sponsor_id).unwrap_or(0);
+        self.sponsorship.pools.insert(&sponsor_id, &pool);
+        let required =
+            Balance::from(env::storage_usage() - initial_storage_usage) * env::storage_byte_cost();
+        let attached = env::attached_deposit();
+        if attached < required {
+            ContractError::InsufficientDeposit.panic_with(&required.to_string());
+        }
+        let pool = pool + attached - required;
+        self.sponsorship.pools.insert(&sponsor_id, &pool);
+        pool.into()
+    }
+
+    /// Withdraws `amount` from the caller's sponsor pool, everything if `None`. Storage paid
+    /// for accounts that are still registered stays with the contract.
+    #[payable]
+    pub fn sponsor_withdraw(&mut self, amount: Option<U128>) -> U128 {
+        assert_one_yocto();
+        let sponsor_id = env::predecessor_account_id();
+        let pool = self.sponsorship.pools.get(&sponsor_id).unwrap_or(0);
+        let amount = amount.map_or(pool, |amount| amount.0);
+        if amount > pool {
+            ContractError::InsufficientSponsorPool.panic_with(sponsor_id.as_str());
+        }
+        self.sponsorship.pools.insert(&sponsor_id, &(pool - amount));
+        if amount > 0 {
+            Promise::new(sponsor_id).transfer(amount);
+        }
+        (pool - amount).into()
+    }
+
+    /// Adds the attached deposit to the global pool, used when the sender has no pool.
+    #[payable]
+    pub fn global_sponsor_deposit(&mut self) -> U128 {
+        self.sponsorship.global_pool += env::attached_deposit();
+        self.sponsorship.global_pool.into()
+    }
+
+    /// Withdraws `amount` from the global pool to the owner.
+    pub fn global_sponsor_withdraw(&mut self, amount: U128) -> U128 {
+        self.abort_if_not_owner();
+        if amount.0 > self.sponsorship.global_pool {
+            ContractError::InsufficientSponsorPool.panic();
+        }
+        self.sponsorship.global_pool -= amount.0;
+        Promise::new(self.owner_id.clone()).transfer(amount.0);
+        self.sponsorship.global_pool.into()
+    }
+
+    pub fn sponsor_pool_of(&self, account_id: AccountId) -> U128 {
+        self.sponsorship.pools.get(&account_id).unwrap_or(0).into()
+    }
+
+    pub fn global_sponsor_pool(&self) -> U128 {
+        self.sponsorship.global_pool.into()
+    }
+
+    pub fn sponsorship_of(&self, account_id: AccountId) -> Option<Sponsorship> {
+        self.sponsorship.accounts.get(&account_id)
+    }
+
+    /// Registers `account_ids` for the storage balance minimum each, paid with the attached
+    /// deposit; the remainder is refunded. Registered accounts are skipped. Returns the accounts
+    /// that were registered. Takes at most `MAX_BATCH_SIZE` accounts.
+    #[payable]
+    pub fn storage_register_batch(&mut self, account_ids: Vec<AccountId>) -> Vec<AccountId> {
+        self.abort_if_pause();
+        abort_if_batch_too_large(&account_ids);
+        let min_balance = self.storage_balance_bounds().min.0;
+        let mut registered = vec![];
+        for account_id in account_ids {
+            if !self.token.accounts.contains_key(&account_id) {
+                self.token.internal_register_account(&account_id);
+                registered.push(account_id);
+            }
+        }
+        let required = min_balance * registered.len() as Balance;
+        let attached = env::attached_deposit();
+        if attached < required {
+            ContractError::InsufficientDeposit.panic_with(&required.to_string());
+        }
+        if attached > required {
+            Promise::new(env::predecessor_account_id()).transfer(attached - required);
+        }
+        registered
+    }
+
+    pub fn is_registered(&self, account_id: AccountId) -> bool {
+        self.token.accounts.contains_key(&account_id)
+    }
+
+    /// `storage_balance_of` of every account in `account_ids`, in the same order.
+    pub fn storage_balance_of_batch(
+        &self,
+        account_ids: Vec<AccountId>,
+    ) -> Vec<Option<StorageBalance>> {
+        abort_if_batch_too_large(&account_ids);
+        account_ids
+            .into_iter()
+            .map(|account_id| self.storage_balance_of(account_id))
+            .collect()
+    }
+
+    /// Whether `storage_unregister(force)` may burn a positive balance.
+    pub fn set_force_close_allowed(&mut self, allowed: bool) {
+        self.abort_if_not_owner();
+        self.force_close_allowed = allowed;
+    }
+
+    pub fn force_close_allowed(&self) -> bool {
+        self.force_close_allowed
+    }
+}
+
+#[near_bindgen]
+impl StorageManagement for Contract {
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        self.token.storage_deposit(account_id, registration_only)
+    }
+
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        self.token.storage_withdraw(amount)
+    }
+
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        if let Some((account_id, balance)) = self.internal_storage_unregister(force) {
+            self.on_account_closed(account_id, balance);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        self.token.storage_balance_bounds()
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.token.storage_balance_of(account_id)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs};
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    use super::*;
+    use crate::test_utils::get_context;
+
+    #[test]
+    fn test_sponsored_registration() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        let pool = contract.sponsor_deposit().0;
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(2), 10.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 10);
+        let sponsorship = contract.sponsorship_of(accounts(2)).unwrap();
+        assert_eq!(sponsorship.sponsor_id, Some(accounts(1)));
+        assert_eq!(
+            contract.sponsor_pool_of(accounts(1)).0,
+            pool - sponsorship.deposit.0
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.ft_transfer(accounts(1), 10.into(), None);
+        assert!(contract.storage_unregister(None));
+        assert_eq!(contract.sponsor_pool_of(accounts(1)).0, pool);
+        assert_eq!(contract.sponsorship_of(accounts(2)), None);
+    }
+
+    #[test]
+    fn test_global_pool() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.global_sponsor_deposit();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.ft_transfer(accounts(2), 10.into(), None);
+        let sponsorship = contract.sponsorship_of(accounts(2)).unwrap();
+        assert_eq!(sponsorship.sponsor_id, None);
+        assert_eq!(
+            contract.global_sponsor_pool().0,
+            ONE_NEAR - sponsorship.deposit.0
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_SPONSOR_LIMIT_EXCEEDED (1201)")]
+    fn test_global_pool_limit_per_sender() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        contract.global_sponsor_deposit();
+
+        testing_env!(context.attached_deposit(1).build());
+        for i in 0..GLOBAL_SPONSORED_PER_DAY {
+            let receiver_id: AccountId = format!("user{}.near", i).parse().unwrap();
+            contract.ft_transfer(receiver_id, 1.into(), None);
+        }
+        contract.ft_transfer(accounts(2), 1.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_POSITIVE_BALANCE (307)")]
+    fn test_sponsored_account_cannot_close_with_balance() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        contract.sponsor_deposit();
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(2), 10.into(), None);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.storage_unregister(None);
+    }
+
+    #[test]
+    fn test_force_close_burns() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.token.internal_register_account(&accounts(2));
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(2), 10.into(), None);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        assert!(contract.storage_unregister(Some(true)));
+        assert_eq!(contract.ft_total_supply().0, 990);
+        assert!(get_logs()
+            .last()
+            .unwrap()
+            .contains(r#""event":"ft_burn","data":[{"owner_id":"charlie","amount":"10","memo":"account closed"}]"#));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_FORCE_CLOSE_DISABLED (306)")]
+    fn test_force_close_disabled() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.set_force_close_allowed(false);
+        testing_env!(context.attached_deposit(1).build());
+        contract.storage_unregister(Some(true));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BANNED (300)")]
+    fn test_banned_account_cannot_close() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.token.internal_register_account(&accounts(2));
+        contract.add_to_blacklist(&accounts(2));
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.storage_unregister(Some(true));
+    }
+
+    #[test]
+    fn test_register_batch() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        let min_balance = contract.storage_balance_bounds().min.0;
+        testing_env!(context.attached_deposit(min_balance * 3).build());
+        let registered =
+            contract.storage_register_batch(vec![accounts(1), accounts(2), accounts(3)]);
+        assert_eq!(registered, vec![accounts(2), accounts(3)]);
+        assert!(contract.is_registered(accounts(3)));
+        assert!(!contract.is_registered(accounts(4)));
+        let balances = contract.storage_balance_of_batch(vec![accounts(2), accounts(4)]);
+        assert_eq!(balances[0].as_ref().unwrap().total.0, min_balance);
+        assert!(balances[1].is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INSUFFICIENT_DEPOSIT (304)")]
+    fn test_register_batch_underpaid() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        let min_balance = contract.storage_balance_bounds().min.0;
+        testing_env!(context.attached_deposit(min_balance).build());
+        contract.storage_register_batch(vec![accounts(2), accounts(3)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BATCH_TOO_LARGE (1300)")]
+    fn test_register_batch_too_large() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        let account_ids = (0..=crate::views::MAX_BATCH_SIZE)
+            .map(|i| format!("user{}.near", i).parse().unwrap())
+            .collect();
+        contract.storage_register_batch(account_ids);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PAUSED (200)")]
+    fn test_register_batch_paused() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.pause();
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        contract.storage_register_batch(vec![accounts(2)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "The account charlie is not registered")]
+    fn test_no_sponsor() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(2), 10.into(), None);
+    }
+}
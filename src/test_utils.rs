@@ -0,0 +1,13 @@
+use near_sdk::test_utils::{accounts, VMContextBuilder};
+use near_sdk::AccountId;
+
+/// Context of a call from `predecessor_account_id`, who also signed it, to the contract at
+/// `accounts(0)`.
+pub(crate) fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
+    let mut builder = VMContextBuilder::new();
+    builder
+        .current_account_id(accounts(0))
+        .signer_account_id(predecessor_account_id.clone())
+        .predecessor_account_id(predecessor_account_id);
+    builder
+}
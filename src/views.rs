@@ -0,0 +1,105 @@
+use crate::*;
+use near_contract_standards::storage_management::StorageBalance;
+
+/// Maximum number of accounts per call of the batch views and `storage_register_batch`, so a
+/// call stays within the gas limit.
+pub const MAX_BATCH_SIZE: usize = 100;
+
+pub(crate) fn abort_if_batch_too_large(account_ids: &[AccountId]) {
+    if account_ids.len() > MAX_BATCH_SIZE {
+        ContractError::BatchTooLarge.panic_with(&MAX_BATCH_SIZE.to_string());
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountInfo {
+    pub account_id: AccountId,
+    pub balance: U128,
+    pub registered: bool,
+    pub blacklist_status: BlackListStatus,
+    /// The part of the balance that can't be moved: all of it for banned accounts.
+    pub frozen: U128,
+    pub storage_balance: Option<StorageBalance>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// `ft_balance_of` of every account in `account_ids`, in the same order.
+    pub fn ft_balances_of(&self, account_ids: Vec<AccountId>) -> Vec<U128> {
+        self.abort_if_pause();
+        abort_if_batch_too_large(&account_ids);
+        account_ids
+            .iter()
+            .map(|account_id| self.token.accounts.get(account_id).unwrap_or(0).into())
+            .collect()
+    }
+
+    /// Balance, registration and blacklist status of every account in `account_ids`, in the
+    /// same order.
+    pub fn account_info(&self, account_ids: Vec<AccountId>) -> Vec<AccountInfo> {
+        self.abort_if_pause();
+        abort_if_batch_too_large(&account_ids);
+        account_ids
+            .into_iter()
+            .map(|account_id| {
+                let balance = self.token.accounts.get(&account_id);
+                let blacklist_status = self.get_blacklist_status(&account_id);
+                let frozen = match blacklist_status {
+                    BlackListStatus::Banned => balance.unwrap_or(0),
+                    BlackListStatus::Allowable => 0,
+                };
+                AccountInfo {
+                    balance: balance.unwrap_or(0).into(),
+                    registered: balance.is_some(),
+                    blacklist_status,
+                    frozen: frozen.into(),
+                    storage_balance: self.storage_balance_of(account_id.clone()),
+                    account_id,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::get_context;
+
+    #[test]
+    fn test_account_info() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.token.internal_register_account(&accounts(2));
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(2), 10.into(), None);
+        contract.add_to_blacklist(&accounts(2));
+
+        assert_eq!(
+            contract.ft_balances_of(vec![accounts(1), accounts(2), accounts(3)]),
+            vec![U128(990), U128(10), U128(0)]
+        );
+        let info = contract.account_info(vec![accounts(2), accounts(3)]);
+        assert_eq!(info[0].account_id, accounts(2));
+        assert_eq!(info[0].blacklist_status, BlackListStatus::Banned);
+        assert_eq!(info[0].frozen.0, 10);
+        assert!(info[0].storage_balance.is_some());
+        assert!(!info[1].registered);
+        assert_eq!(info[1].frozen.0, 0);
+        assert!(info[1].storage_balance.is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BATCH_TOO_LARGE (1300)")]
+    fn test_batch_too_large() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = Contract::new_default_meta(accounts(1), 1_000.into());
+        contract.ft_balances_of(vec![accounts(2); MAX_BATCH_SIZE + 1]);
+    }
+}
@@ -0,0 +1,100 @@
+use near_sdk::serde_json::json;
+
+use crate::utils::*;
+
+#[test]
+fn test_blacklisted_sender_transfer_call() {
+    let env = init();
+    env.transfer(&env.root, &env.alice.account_id(), 100)
+        .assert_success();
+    env.call(
+        &env.root,
+        "add_to_blacklist",
+        json!({ "account_id": env.alice.account_id() }),
+        0,
+    )
+    .assert_success();
+    let result = env.transfer_call(&env.alice, &env.receiver.account_id(), 10, "accept");
+    assert_failure(result, "ERR_BANNED");
+    assert_eq!(env.balance_of(&env.alice.account_id()), 100);
+}
+
+#[test]
+fn test_blacklisted_receiver_contract() {
+    let env = init();
+    env.call(
+        &env.root,
+        "add_to_blacklist",
+        json!({ "account_id": env.receiver.account_id() }),
+        0,
+    )
+    .assert_success();
+    // By default only senders are checked.
+    env.transfer_call(&env.root, &env.receiver.account_id(), 10, "accept")
+        .assert_success();
+
+    env.call(
+        &env.root,
+        "set_blacklist_mode",
+        json!({ "mode": "SenderAndReceiver" }),
+        0,
+    )
+    .assert_success();
+    let result = env.transfer_call(&env.root, &env.receiver.account_id(), 10, "accept");
+    assert_failure(result, "ERR_BANNED");
+    assert_eq!(env.balance_of(&env.receiver.account_id()), 10);
+}
+
+#[test]
+fn test_blacklisted_contract_cannot_forward() {
+    let env = init();
+    env.transfer_call(&env.root, &env.receiver.account_id(), 100, "accept")
+        .assert_success();
+    env.call(
+        &env.root,
+        "add_to_blacklist",
+        json!({ "account_id": env.receiver.account_id() }),
+        0,
+    )
+    .assert_success();
+
+    // The receiver's own call succeeds, the `ft_transfer` it sends to the token fails.
+    let result = env.alice.call(
+        env.receiver.account_id(),
+        "forward",
+        &json!({ "receiver_id": env.alice.account_id(), "amount": "50" })
+            .to_string()
+            .into_bytes(),
+        near_sdk_sim::DEFAULT_GAS,
+        0,
+    );
+    assert_failure(result, "ERR_BANNED");
+    assert_eq!(env.balance_of(&env.receiver.account_id()), 100);
+    assert_eq!(env.balance_of(&env.alice.account_id()), 0);
+}
+
+#[test]
+fn test_pause_across_contracts() {
+    let env = init();
+    env.transfer_call(&env.root, &env.receiver.account_id(), 100, "accept")
+        .assert_success();
+    env.call(&env.root, "pause", json!({}), 0).assert_success();
+
+    let result = env.transfer_call(&env.root, &env.receiver.account_id(), 10, "accept");
+    assert_failure(result, "ERR_PAUSED");
+    let result = env.alice.call(
+        env.receiver.account_id(),
+        "forward",
+        &json!({ "receiver_id": env.alice.account_id(), "amount": "50" })
+            .to_string()
+            .into_bytes(),
+        near_sdk_sim::DEFAULT_GAS,
+        0,
+    );
+    assert_failure(result, "ERR_PAUSED");
+
+    env.call(&env.root, "resume", json!({}), 0).assert_success();
+    env.transfer_call(&env.root, &env.receiver.account_id(), 10, "accept")
+        .assert_success();
+    assert_eq!(env.balance_of(&env.receiver.account_id()), 110);
+}
@@ -0,0 +1,87 @@
+use near_sdk::json_types::U128;
+use near_sdk::serde_json::{json, Value};
+use near_sdk_sim::{init_simulator, to_yocto, UserAccount, DEFAULT_GAS};
+
+use crate::utils::*;
+
+fn deploy_factory(root: &UserAccount) -> UserAccount {
+    let factory = root.deploy(
+        &FACTORY_WASM_BYTES,
+        "factory".parse().unwrap(),
+        to_yocto("100"),
+    );
+    root.call(
+        factory.account_id(),
+        "new",
+        &json!({ "owner_id": root.account_id() })
+            .to_string()
+            .into_bytes(),
+        DEFAULT_GAS,
+        0,
+    )
+    .assert_success();
+    root.call(
+        factory.account_id(),
+        "store_code",
+        &TOKEN_WASM_BYTES,
+        DEFAULT_GAS,
+        0,
+    )
+    .assert_success();
+    factory
+}
+
+fn view(root: &UserAccount, account_id: &str, method: &str) -> Value {
+    root.view(account_id.parse().unwrap(), method, b"{}")
+        .unwrap_json_value()
+}
+
+#[test]
+fn test_upgrade_instance_with_upgrade_deposit() {
+    let root = init_simulator(None);
+    let factory = deploy_factory(&root);
+    let deposit: U128 =
+        near_sdk::serde_json::from_value(view(&root, "factory", "required_deposit")).unwrap();
+    let args = json!({
+        "prefix": "eurt",
+        "args": {
+            "owner_id": root.account_id(),
+            "total_supply": U128(1_000),
+            "metadata": {
+                "spec": "ft-1.0.0",
+                "name": "Euro Tether",
+                "symbol": "EURt",
+                "icon": null,
+                "reference": null,
+                "reference_hash": null,
+                "decimals": 6,
+            },
+        },
+    });
+    root.call(
+        factory.account_id(),
+        "create_token",
+        &args.to_string().into_bytes(),
+        DEFAULT_GAS,
+        deposit.0,
+    )
+    .assert_success();
+    assert_eq!(view(&root, "factory", "token_count"), 1);
+
+    let deposit: U128 =
+        near_sdk::serde_json::from_value(view(&root, "factory", "upgrade_deposit")).unwrap();
+    let result = root.call(
+        factory.account_id(),
+        "upgrade_tokens",
+        &json!({ "from_index": 0, "limit": 10 })
+            .to_string()
+            .into_bytes(),
+        DEFAULT_GAS,
+        deposit.0,
+    );
+    result.assert_success();
+    assert!(result.promise_errors().is_empty());
+    let history = view(&root, "eurt.factory", "code_history");
+    assert_eq!(history.as_array().unwrap().len(), 2);
+    assert_eq!(view(&root, "eurt.factory", "ft_total_supply"), "1000");
+}
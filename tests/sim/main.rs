@@ -0,0 +1,9 @@
+//! Simulation tests of flows that span several contracts or transactions. They need the wasm
+//! binaries, see "Simulation tests" in the README.
+
+mod blacklist;
+mod factory;
+mod receiver;
+mod storage;
+mod upgrade;
+mod utils;
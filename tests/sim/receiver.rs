@@ -0,0 +1,59 @@
+use near_sdk::json_types::U128;
+
+use crate::utils::*;
+
+#[test]
+fn test_transfer_call_accept() {
+    let env = init();
+    let result = env.transfer_call(&env.root, &env.receiver.account_id(), 100, "accept");
+    result.assert_success();
+    assert_eq!(result.unwrap_json::<U128>().0, 100);
+    assert_eq!(env.balance_of(&env.receiver.account_id()), 100);
+    assert_eq!(env.balance_of(&env.root.account_id()), TOTAL_SUPPLY - 100);
+}
+
+#[test]
+fn test_transfer_call_partial_refund() {
+    let env = init();
+    let result = env.transfer_call(&env.root, &env.receiver.account_id(), 100, "refund:30");
+    result.assert_success();
+    assert_eq!(result.unwrap_json::<U128>().0, 70);
+    assert_eq!(env.balance_of(&env.receiver.account_id()), 70);
+    assert_eq!(env.balance_of(&env.root.account_id()), TOTAL_SUPPLY - 70);
+    assert_eq!(env.total_supply(), TOTAL_SUPPLY);
+}
+
+#[test]
+fn test_transfer_call_receiver_panics() {
+    let env = init();
+    let result = env.transfer_call(&env.root, &env.receiver.account_id(), 100, "panic");
+    // The transfer itself succeeds and `ft_resolve_transfer` refunds everything.
+    assert_eq!(result.unwrap_json::<U128>().0, 0);
+    assert_failure(result, "ft_on_transfer panicked");
+    assert_eq!(env.balance_of(&env.receiver.account_id()), 0);
+    assert_eq!(env.balance_of(&env.root.account_id()), TOTAL_SUPPLY);
+}
+
+#[test]
+fn test_transfer_call_refund_all() {
+    let env = init();
+    env.transfer(&env.root, &env.alice.account_id(), 100)
+        .assert_success();
+    env.transfer_call(&env.alice, &env.receiver.account_id(), 100, "refund_all")
+        .assert_success();
+    assert_eq!(env.balance_of(&env.alice.account_id()), 100);
+    env.transfer_call(&env.alice, &env.receiver.account_id(), 100, "accept")
+        .assert_success();
+    assert_eq!(env.balance_of(&env.receiver.account_id()), 100);
+    assert_eq!(env.total_supply(), TOTAL_SUPPLY);
+}
+
+#[test]
+fn test_transfer_call_to_non_receiver() {
+    let env = init();
+    // Alice is an account without code, so `ft_on_transfer` fails and everything is refunded.
+    let result = env.transfer_call(&env.root, &env.alice.account_id(), 100, "accept");
+    assert_eq!(result.unwrap_json::<U128>().0, 0);
+    assert_eq!(env.balance_of(&env.alice.account_id()), 0);
+    assert_eq!(env.balance_of(&env.root.account_id()), TOTAL_SUPPLY);
+}
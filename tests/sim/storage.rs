@@ -0,0 +1,90 @@
+use near_sdk::serde_json::json;
+use near_sdk_sim::to_yocto;
+
+use crate::utils::*;
+
+#[test]
+fn test_unregister_refunds_storage() {
+    let env = init();
+    let before = env.alice.account().unwrap().amount;
+    env.call(&env.alice, "storage_unregister", json!({}), 1)
+        .assert_success();
+    assert!(env.alice.account().unwrap().amount > before);
+    assert_eq!(
+        env.view(
+            "is_registered",
+            json!({ "account_id": env.alice.account_id() })
+        ),
+        false
+    );
+}
+
+#[test]
+fn test_force_close_burns_balance() {
+    let env = init();
+    env.transfer(&env.root, &env.alice.account_id(), 100)
+        .assert_success();
+    let result = env.call(&env.alice, "storage_unregister", json!({}), 1);
+    assert_failure(result, "positive balance");
+
+    let result = env.call(
+        &env.alice,
+        "storage_unregister",
+        json!({ "force": true }),
+        1,
+    );
+    result.assert_success();
+    assert!(result
+        .logs()
+        .iter()
+        .any(|log| log.contains(r#""event":"ft_burn""#) && log.contains("account closed")));
+    assert_eq!(env.total_supply(), TOTAL_SUPPLY - 100);
+}
+
+#[test]
+fn test_banned_account_cannot_unregister() {
+    let env = init();
+    env.call(
+        &env.root,
+        "add_to_blacklist",
+        json!({ "account_id": env.alice.account_id() }),
+        0,
+    )
+    .assert_success();
+    let result = env.call(
+        &env.alice,
+        "storage_unregister",
+        json!({ "force": true }),
+        1,
+    );
+    assert_failure(result, "ERR_BANNED");
+}
+
+#[test]
+fn test_sponsored_transfer() {
+    let env = init();
+    env.call(&env.root, "sponsor_deposit", json!({}), to_yocto("1"))
+        .assert_success();
+    let bob = env.root.create_user("bob".parse().unwrap(), to_yocto("10"));
+    env.transfer(&env.root, &bob.account_id(), 100)
+        .assert_success();
+    assert_eq!(env.balance_of(&bob.account_id()), 100);
+
+    env.transfer(&bob, &env.root.account_id(), 100)
+        .assert_success();
+    let pool_of_root = || -> u128 {
+        env.view(
+            "sponsor_pool_of",
+            json!({ "account_id": env.root.account_id() }),
+        )
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap()
+    };
+    let pool = pool_of_root();
+    env.call(&bob, "storage_unregister", json!({}), 1)
+        .assert_success();
+    // The storage deposit goes back to the pool, not to bob.
+    assert!(pool_of_root() > pool);
+}
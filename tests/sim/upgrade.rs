@@ -0,0 +1,88 @@
+use near_sdk::serde_json::json;
+use near_sdk_sim::{to_yocto, DEFAULT_GAS};
+
+use crate::utils::*;
+
+#[test]
+fn test_upgrade_from_previous_version() {
+    let env = init_with_code(&PREVIOUS_TOKEN_WASM_BYTES);
+    env.transfer(&env.root, &env.alice.account_id(), 100)
+        .assert_success();
+    env.call(
+        &env.root,
+        "add_to_blacklist",
+        json!({ "account_id": env.alice.account_id() }),
+        0,
+    )
+    .assert_success();
+
+    env.root
+        .call(
+            env.token.account_id(),
+            "upgrade",
+            &TOKEN_WASM_BYTES,
+            DEFAULT_GAS,
+            0,
+        )
+        .assert_success();
+
+    // The previous version has no holder index, which has to be built before transfers resume.
+    assert_eq!(env.view("contract_status", json!({})), "Migrating");
+    let accounts = json!({ "account_ids": [env.root.account_id(), env.alice.account_id()] });
+    env.call(&env.root, "enqueue_migration_accounts", accounts, 0)
+        .assert_success();
+    env.call(&env.root, "migrate_step", json!({ "limit": 10 }), 0)
+        .assert_success();
+    env.call(&env.root, "finish_migration", json!({}), 0)
+        .assert_success();
+    assert_eq!(env.view("ft_holder_count", json!({})), 2);
+
+    // `migrate` brought the state of the previous version to the current layout.
+    assert_eq!(env.balance_of(&env.alice.account_id()), 100);
+    assert_eq!(env.total_supply(), TOTAL_SUPPLY);
+    assert_eq!(
+        env.view(
+            "get_blacklist_status",
+            json!({ "account_id": env.alice.account_id() })
+        ),
+        "Banned"
+    );
+    assert_eq!(env.view("contract_status", json!({})), "Working");
+    env.transfer_call(&env.root, &env.receiver.account_id(), 10, "accept")
+        .assert_success();
+}
+
+#[test]
+fn test_upgrade_by_non_owner() {
+    let env = init();
+    let result = env.alice.call(
+        env.token.account_id(),
+        "upgrade",
+        &TOKEN_WASM_BYTES,
+        DEFAULT_GAS,
+        0,
+    );
+    assert_failure(result, "ERR_MISSING_ROLE");
+}
+
+#[test]
+fn test_upgrade_keeps_previous_code() {
+    let env = init();
+    // Pays for the copy of the code kept for `rollback`, the rest is refunded.
+    env.root
+        .call(
+            env.token.account_id(),
+            "upgrade",
+            &TOKEN_WASM_BYTES,
+            DEFAULT_GAS,
+            to_yocto("10"),
+        )
+        .assert_success();
+    // The deployment recorded at initialization, then the upgrade with the hash of its code.
+    let history = env.view("code_history", json!({}));
+    let history = history.as_array().unwrap();
+    assert_eq!(history.len(), 2);
+    assert!(history[0]["code_hash"].is_null());
+    assert!(history[1]["code_hash"].is_string());
+    assert_eq!(env.balance_of(&env.root.account_id()), TOTAL_SUPPLY);
+}
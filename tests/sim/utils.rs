@@ -0,0 +1,163 @@
+use near_sdk::json_types::U128;
+use near_sdk::serde_json::{json, Value};
+use near_sdk::AccountId;
+use near_sdk_sim::{init_simulator, to_yocto, ExecutionResult, UserAccount, DEFAULT_GAS};
+
+near_sdk_sim::lazy_static_include::lazy_static_include_bytes! {
+    pub TOKEN_WASM_BYTES => "target/wasm32-unknown-unknown/release/tether_token.wasm",
+    pub RECEIVER_WASM_BYTES => "target/wasm32-unknown-unknown/release/mock_receiver.wasm",
+    pub FACTORY_WASM_BYTES => "target/wasm32-unknown-unknown/release/tether_token_factory.wasm",
+    pub PREVIOUS_TOKEN_WASM_BYTES => "res/tether_token_previous.wasm",
+}
+
+pub const TOTAL_SUPPLY: u128 = 1_000_000;
+
+pub struct Env {
+    pub root: UserAccount,
+    pub token: UserAccount,
+    pub receiver: UserAccount,
+    pub alice: UserAccount,
+}
+
+/// `root` owns the token and its whole supply, `receiver` is the mock receiver contract.
+/// `alice` and `receiver` are registered.
+pub fn init() -> Env {
+    init_with_code(&TOKEN_WASM_BYTES)
+}
+
+pub fn init_with_code(token_code: &[u8]) -> Env {
+    let root = init_simulator(None);
+    let token = root.deploy(token_code, "token".parse().unwrap(), to_yocto("100"));
+    root.call(
+        token.account_id(),
+        "new_default_meta",
+        &json!({ "owner_id": root.account_id(), "total_supply": U128(TOTAL_SUPPLY) })
+            .to_string()
+            .into_bytes(),
+        DEFAULT_GAS,
+        0,
+    )
+    .assert_success();
+
+    let receiver = root.deploy(
+        &RECEIVER_WASM_BYTES,
+        "receiver".parse().unwrap(),
+        to_yocto("10"),
+    );
+    root.call(
+        receiver.account_id(),
+        "new",
+        &json!({ "token_id": token.account_id() })
+            .to_string()
+            .into_bytes(),
+        DEFAULT_GAS,
+        0,
+    )
+    .assert_success();
+
+    let alice = root.create_user("alice".parse().unwrap(), to_yocto("100"));
+    let env = Env {
+        root,
+        token,
+        receiver,
+        alice,
+    };
+    env.register(&env.alice.account_id());
+    env.register(&env.receiver.account_id());
+    env
+}
+
+impl Env {
+    /// Calls `method` of the token as `signer`.
+    pub fn call(
+        &self,
+        signer: &UserAccount,
+        method: &str,
+        args: Value,
+        deposit: u128,
+    ) -> ExecutionResult {
+        signer.call(
+            self.token.account_id(),
+            method,
+            &args.to_string().into_bytes(),
+            DEFAULT_GAS,
+            deposit,
+        )
+    }
+
+    pub fn view(&self, method: &str, args: Value) -> Value {
+        self.root
+            .view(
+                self.token.account_id(),
+                method,
+                &args.to_string().into_bytes(),
+            )
+            .unwrap_json_value()
+    }
+
+    pub fn register(&self, account_id: &AccountId) {
+        self.call(
+            &self.root,
+            "storage_deposit",
+            json!({ "account_id": account_id }),
+            to_yocto("0.01"),
+        )
+        .assert_success();
+    }
+
+    pub fn balance_of(&self, account_id: &AccountId) -> u128 {
+        let balance: U128 = near_sdk::serde_json::from_value(
+            self.view("ft_balance_of", json!({ "account_id": account_id })),
+        )
+        .unwrap();
+        balance.0
+    }
+
+    pub fn total_supply(&self) -> u128 {
+        let supply: U128 =
+            near_sdk::serde_json::from_value(self.view("ft_total_supply", json!({}))).unwrap();
+        supply.0
+    }
+
+    pub fn transfer(&self, from: &UserAccount, to: &AccountId, amount: u128) -> ExecutionResult {
+        self.call(
+            from,
+            "ft_transfer",
+            json!({ "receiver_id": to, "amount": U128(amount) }),
+            1,
+        )
+    }
+
+    pub fn transfer_call(
+        &self,
+        from: &UserAccount,
+        to: &AccountId,
+        amount: u128,
+        msg: &str,
+    ) -> ExecutionResult {
+        self.call(
+            from,
+            "ft_transfer_call",
+            json!({ "receiver_id": to, "amount": U128(amount), "msg": msg }),
+            1,
+        )
+    }
+}
+
+/// Asserts that the transaction, or one of the receipts it spawned, failed with `expected`.
+pub fn assert_failure(result: ExecutionResult, expected: &str) {
+    let mut statuses = vec![format!("{:?}", result.status())];
+    statuses.extend(
+        result
+            .promise_errors()
+            .into_iter()
+            .flatten()
+            .map(|error| format!("{:?}", error.status())),
+    );
+    assert!(
+        statuses.iter().any(|status| status.contains(expected)),
+        "Expected a failure with {}, got {:?}",
+        expected,
+        statuses
+    );
+}